@@ -1,41 +1,127 @@
 use iou::*;
 use libiouring as iou;
-use std::alloc::{alloc, Layout};
-use std::mem::size_of;
-use std::net::TcpListener;
-use std::os::unix::io::AsRawFd;
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
 use std::ptr::null_mut;
+
+/// What each request in flight is for.
+enum Request {
+    Accept,
+    /// Receives on the connection, which is closed once they stop.
+    Recv(OwnedFd),
+}
+
 fn main() -> std::io::Result<()> {
     const QDEPTH: u32 = 32;
-    let connect = TcpListener::bind("127.0.0.1:8989")?;
+    const SOCKET_DATA: u64 = 21;
+    const GROUP_ID: u16 = 0xf;
+    let addr: SocketAddr = "127.0.0.1:8989".parse().unwrap();
+    let mut ring = IoUring::builder()
+        .entries(QDEPTH)
+        .auto_flush_overflow()
+        .build()?;
+    // Multishot accept and receive are flags rather than opcodes, so the
+    // probe cannot tell whether they are supported, and an unsupported flag
+    // only fails once the request runs. Instead check for the opcodes added
+    // in the same releases: socket along with multishot accept in 5.19, and
+    // zero-copy send along with multishot receive in 6.0.
+    let probe = ring.probe()?;
+    for (op, needed_for) in [
+        (Opcode::Socket, "socket creation and multishot accept"),
+        (Opcode::SendZc, "multishot receive"),
+    ] {
+        if !probe.supports(op) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!(
+                    "kernel lacks {:?}, needed for {}; Linux 6.0 or later is required",
+                    op, needed_for
+                ),
+            ));
+        }
+    }
+    drop(probe);
+    ring.io_uring_get_sqe()
+        .unwrap()
+        .io_uring_prep_socket(libc::AF_INET, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0, 0)
+        .set_sqe_data(SOCKET_DATA)
+        .finalize();
+    ring.submit();
+    let connect = {
+        let cqes = io_uring_wait_cqe(&mut ring)?.unwrap();
+        let fd = cqes.peek(0).unwrap().result()?;
+        unsafe { OwnedFd::from_raw_fd(fd as i32) }
+    };
     let cfd = connect.as_raw_fd();
-    let mut ring = IoUring::init(QDEPTH as isize);
+    bind_and_listen(cfd, &addr, 128)?;
     const BUFS: usize = 64;
-    let mut br = BufRing::init_with_group_id(&mut ring, 0xf, BUFS as u32, 1024).unwrap();
+    let br = BufRing::init_with_group_id(&mut ring, GROUP_ID, BUFS as u32, 1024)?;
+    let mut requests = Registry::new();
     // Initialize io_uring, set things when necessary.
     let entry = ring.io_uring_get_sqe().unwrap();
     entry
         .io_uring_prep_multishot_accept(cfd, null_mut(), null_mut(), 0)
-        .set_sqe_data(22)
+        .set_sqe_data(requests.insert(Request::Accept).user_data())
         .finalize();
     let out = ring.submit();
     println!("Wait finished, got {}", out);
-    let cqes = io_uring_wait_cqe_nr(&mut ring, 2).unwrap().unwrap();
-    for i in 0..2 {
-        let c = cqes.peek(i).unwrap();
-        println!(
-            "{} Wait finished, got {}, {}",
-            i,
-            c.get_cqe_data(),
-            c.get_result()
-        );
+    let mut overflow = ring.cq_overflow();
+    loop {
+        // Connections accepted in this batch, or whose receive ran out of
+        // buffers, we can only post receives for them once we are done with
+        // the CQEs.
+        let mut accepted = Vec::new();
+        let cqes = io_uring_wait_cqe(&mut ring)?.unwrap();
+        for c in cqes {
+            let Some(done) = requests.dispatch(&c) else {
+                println!("Stale completion {:#x}", c.get_cqe_data());
+                continue;
+            };
+            match done.value() {
+                Request::Accept => {
+                    println!("Accepted {}", c.get_result());
+                    if c.get_result() >= 0 {
+                        accepted.push(unsafe { OwnedFd::from_raw_fd(c.get_result()) });
+                    }
+                    if done.is_last() {
+                        println!("Accept stopped");
+                    }
+                }
+                Request::Recv(fd) => {
+                    if let Some(buf) = br.get_for_cqe(&c) {
+                        // The buffer is handed back to the kernel once `buf`
+                        // is dropped.
+                        println!("{} received {} bytes", fd.as_raw_fd(), buf.len());
+                    } else {
+                        println!("{} receive done, got {}", fd.as_raw_fd(), c.get_result());
+                    }
+                }
+            }
+            if let Completion::Last(Request::Recv(fd)) = done {
+                if c.get_result() == -libc::ENOBUFS {
+                    accepted.push(fd);
+                } else {
+                    println!("Closing {}", fd.as_raw_fd());
+                }
+            }
+        }
+        // A burst of connections can overrun the CQ.
+        if ring.cq_overflow() != overflow {
+            overflow = ring.cq_overflow();
+            println!(
+                "CQ overflowed, flushed {} times, {} completions lost",
+                overflow.flushes, overflow.dropped
+            );
+        }
+        br.flush();
+        for fd in accepted {
+            let raw = fd.as_raw_fd();
+            let data = requests.insert(Request::Recv(fd)).user_data();
+            ring.get_sqe_or_submit()?
+                .io_uring_prep_recv_multishot(raw, GROUP_ID, 0)
+                .set_sqe_data(data)
+                .finalize();
+        }
+        ring.submit();
     }
-    drop(cqes);
-    println!(
-        "After drop: CQEs {} SQEs R {} SQEs A {}",
-        ring.io_uring_cq_ready(),
-        ring.io_uring_sq_ready(),
-        ring.io_uring_sq_available()
-    );
-    Ok(())
 }
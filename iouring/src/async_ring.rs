@@ -0,0 +1,84 @@
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use tokio::io::unix::AsyncFd;
+
+use super::*;
+
+/// An `IoUring` driven from a tokio runtime. The ring signals an eventfd
+/// whenever a CQE is posted, which tokio polls alongside everything else, so
+/// waiting for completions does not block the runtime's thread.
+///
+/// Like `IoUring`, this is not `Send`, so a future holding it cannot be
+/// passed to `tokio::spawn`. Run it with `tokio::task::spawn_local` on a
+/// `LocalSet`, or with `block_on`.
+///
+/// ```ignore
+/// let local = tokio::task::LocalSet::new();
+/// local.spawn_local(async move {
+///     let mut ring = AsyncIoUring::new(IoUring::init(64)?)?;
+///     // ...
+/// });
+/// local.await;
+/// ```
+pub struct AsyncIoUring {
+    ring: IoUring,
+    eventfd: AsyncFd<OwnedFd>,
+}
+
+impl Drop for AsyncIoUring {
+    fn drop(&mut self) {
+        let _ = self.ring.unregister_eventfd();
+    }
+}
+
+impl AsyncIoUring {
+    /// Wrap `ring`, registering a new eventfd with it.
+    ///
+    /// # Panics
+    /// If called outside of a tokio runtime.
+    pub fn new(mut ring: IoUring) -> std::io::Result<AsyncIoUring> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        ring.register_eventfd(fd.as_raw_fd())?;
+        match AsyncFd::new(fd) {
+            Ok(eventfd) => Ok(AsyncIoUring { ring, eventfd }),
+            Err(e) => {
+                let _ = ring.unregister_eventfd();
+                Err(e)
+            }
+        }
+    }
+
+    pub fn get_ref(&self) -> &IoUring {
+        &self.ring
+    }
+
+    /// Returns the ring, e.g., to queue SQEs and submit them.
+    pub fn get_mut(&mut self) -> &mut IoUring {
+        &mut self.ring
+    }
+
+    /// Wait for CQEs to be available and return them, without blocking the
+    /// thread. As with `io_uring_peek_cqe`, this returns `None` if the only
+    /// CQEs were internal ones.
+    pub async fn wait_cqe(&mut self) -> std::io::Result<Option<CqeJar<'_>>> {
+        while self.ring.io_uring_cq_ready() == 0 {
+            let mut guard = self.eventfd.readable().await?;
+            // Reset the counter, the CQ is what tells us what completed. A
+            // CQE posted after this signals the eventfd again.
+            let mut count = 0u64;
+            unsafe {
+                libc::read(
+                    guard.get_inner().as_raw_fd(),
+                    &mut count as *mut u64 as *mut libc::c_void,
+                    std::mem::size_of::<u64>(),
+                )
+            };
+            guard.clear_ready();
+        }
+        unsafe { io_uring_peek_cqe(&mut self.ring) }
+    }
+}
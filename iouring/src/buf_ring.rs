@@ -1,23 +1,78 @@
 extern crate static_assertions as sa;
 use std::alloc::{alloc, dealloc, Layout};
+use std::cell::Cell;
 use std::mem::size_of;
+use std::ops::Deref;
 use std::sync::atomic::AtomicU16;
 
 use super::*;
 
+/// Number of recycled buffers that are staged before the ring tail
+/// is published to the kernel.
+const DEFAULT_RECYCLE_BATCH: u16 = 16;
+
+/// A ring of provided buffers registered with an `IoUring`. The buffer
+/// group is unregistered before its memory is freed on drop.
 pub struct BufRing {
+    handle: RingHandle,
     buffers: *mut io_uring_buf_ring,
     layout: Layout,
     reg: io_uring_buf_reg,
     io_bufs: *mut u8,
     io_layout: Layout,
     entry_size: usize,
+    // Buffers that have been written past the tail, but not yet
+    // published to the kernel.
+    pending: Cell<u16>,
+    batch: u16,
+    // Bitset of the buffers held by a `BufGuard`.
+    taken: Box<[Cell<u64>]>,
     pub mask: u32,
 }
 
+/// A buffer the kernel picked from a `BufRing` for a completed request.
+/// The buffer is handed back to the ring when the guard is dropped.
+pub struct BufGuard<'a> {
+    ring: &'a BufRing,
+    bid: u16,
+    len: usize,
+}
+
+impl BufGuard<'_> {
+    /// Returns the ID of the underlying buffer.
+    pub fn bid(&self) -> u16 {
+        self.bid
+    }
+}
+
+impl Deref for BufGuard<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ring.buffer_addr(self.bid), self.len) }
+    }
+}
+
+impl Drop for BufGuard<'_> {
+    fn drop(&mut self) {
+        self.ring.recycle(self.bid)
+    }
+}
+
 impl Drop for BufRing {
     fn drop(&mut self) {
+        let reg = io_uring_buf_reg {
+            bgid: self.reg.bgid,
+            ..Default::default()
+        };
         unsafe {
+            // This fails if registration never succeeded, in which case
+            // the kernel does not reference our memory either.
+            let _ = self.handle.register(
+                IORING_UNREGISTER_PBUF_RING,
+                &reg as *const io_uring_buf_reg as *const libc::c_void,
+                1,
+            );
             dealloc(self.buffers as *mut u8, self.layout);
             dealloc(self.io_bufs, self.io_layout);
         }
@@ -42,7 +97,7 @@ impl BufRing {
     }
 
     #[inline(always)]
-    pub fn ring_update_tail(&mut self, tail: u16) {
+    pub fn ring_update_tail(&self, tail: u16) {
         unsafe {
             let atomic_tail = AtomicU16::from_mut(
                 &mut (*self.buffers)
@@ -56,7 +111,7 @@ impl BufRing {
     }
 
     #[inline(always)]
-    unsafe fn get_buffers(&mut self) -> *mut io_uring_buf {
+    unsafe fn get_buffers(&self) -> *mut io_uring_buf {
         // [apanda] This is too clever, so an explanation follows.
         // This (and the very ugly) bindgen definition are because
         // the kernel is cleverly trying to space makes `io_uring_buf`
@@ -93,7 +148,7 @@ impl BufRing {
     }
 
     #[inline(always)]
-    unsafe fn set_buffer_at_idx(&mut self, offset: usize, addr: *mut u8, len: usize, bid: u16) {
+    unsafe fn set_buffer_at_idx(&self, offset: usize, addr: *mut u8, len: usize, bid: u16) {
         // struct io_uring_buf {
         //     __u64	addr;
         //     __u32	len;
@@ -112,7 +167,7 @@ impl BufRing {
         for i in 0..self.reg.ring_entries {
             self.set_buffer_at_idx(
                 i as usize,
-                self.io_bufs.offset(self.entry_size as isize * i as isize),
+                self.buffer_addr(i as u16),
                 self.entry_size,
                 i as u16,
             );
@@ -121,6 +176,85 @@ impl BufRing {
         self.ring_update_tail(new_tail as u16);
     }
 
+    #[inline(always)]
+    fn buffer_addr(&self, bid: u16) -> *mut u8 {
+        unsafe { self.io_bufs.add(self.entry_size * bid as usize) }
+    }
+
+    /// Return buffer `bid` to the ring. The buffer is placed after any
+    /// other staged buffers, and the tail is published once `batch`
+    /// buffers are staged.
+    fn recycle(&self, bid: u16) {
+        let word = &self.taken[bid as usize / 64];
+        word.set(word.get() & !(1 << (bid % 64)));
+        let pending = self.pending.get();
+        unsafe {
+            self.set_buffer_at_idx(
+                pending as usize,
+                self.buffer_addr(bid),
+                self.entry_size,
+                bid,
+            )
+        };
+        self.pending.set(pending + 1);
+        if pending + 1 >= self.batch {
+            self.flush();
+        }
+    }
+
+    /// Publish any staged buffers to the kernel. Call this before waiting
+    /// for completions if the kernel may have run out of buffers.
+    pub fn flush(&self) {
+        let pending = self.pending.replace(0);
+        if pending > 0 {
+            let tail = unsafe { self.get_tail() } as u16;
+            self.ring_update_tail(tail.wrapping_add(pending));
+        }
+    }
+
+    /// Set how many returned buffers are staged before they are published
+    /// to the kernel. Larger batches mean fewer atomic tail updates, but
+    /// leave the kernel with fewer buffers in the meantime.
+    pub fn set_recycle_batch(&mut self, batch: u16) {
+        self.flush();
+        self.batch = batch.clamp(1, self.reg.ring_entries.min(u16::MAX as u32) as u16);
+    }
+
+    /// Returns buffer `bid` holding `len` bytes of data, as reported by a
+    /// completion. The buffer is returned to the ring when the guard is
+    /// dropped.
+    ///
+    /// # Panics
+    /// If `bid` or `len` are out of range for this ring, or `bid` is already
+    /// held by another guard, i.e., was taken twice for one completion.
+    pub fn get(&self, bid: u16, len: usize) -> BufGuard<'_> {
+        assert!(
+            (bid as u32) < self.reg.ring_entries,
+            "invalid buffer ID {}",
+            bid
+        );
+        assert!(len <= self.entry_size, "length {} exceeds buffer size", len);
+        let word = &self.taken[bid as usize / 64];
+        assert!(
+            word.get() & (1 << (bid % 64)) == 0,
+            "buffer {} is already taken",
+            bid
+        );
+        word.set(word.get() | 1 << (bid % 64));
+        BufGuard {
+            ring: self,
+            bid,
+            len,
+        }
+    }
+
+    /// Returns the buffer the kernel selected for `cqe`, or `None` if no
+    /// buffer was used (e.g., because the request failed).
+    pub fn get_for_cqe(&self, cqe: &io_uring_cqe) -> Option<BufGuard<'_>> {
+        cqe.buffer_id()
+            .map(|bid| self.get(bid, cqe.res.max(0) as usize))
+    }
+
     /// Initialize a buffer ring with a given group ID and entries.
     /// Note, for convenience this also allocates
     pub fn init_with_group_id(
@@ -142,6 +276,7 @@ impl BufRing {
             let io_layout = Layout::from_size_align((entries as usize) * entry_size, 4096).unwrap();
             let io_bufs = unsafe { alloc(io_layout) as *mut u8 };
             let mut ret = BufRing {
+                handle: ring.handle.clone(),
                 buffers: buffers,
                 layout: layout,
                 reg: io_uring_buf_reg {
@@ -153,12 +288,17 @@ impl BufRing {
                 io_bufs: io_bufs,
                 io_layout: io_layout,
                 entry_size: entry_size,
+                pending: Cell::new(0),
+                taken: (0..(entries as usize).div_ceil(64))
+                    .map(|_| Cell::new(0))
+                    .collect(),
+                batch: DEFAULT_RECYCLE_BATCH.min(entries.min(u16::MAX as u32) as u16),
                 mask: entries - 1,
             };
             unsafe {
                 let out = io_uring_register_buf_ring(ring.get_ring_ptr(), &mut ret.reg, 0);
                 if out != 0 {
-                    panic!("Registration failed");
+                    return Err(std::io::Error::from_raw_os_error(-out));
                 }
                 ret.ring_init();
                 ret.add_all_buffers();
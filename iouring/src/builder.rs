@@ -0,0 +1,158 @@
+use std::os::fd::BorrowedFd;
+use std::time::Duration;
+
+use super::*;
+
+/// Number of SQ entries used when the builder is not given one.
+const DEFAULT_ENTRIES: u32 = 64;
+
+/// Builder for an [`IoUring`], used to pass setup flags to the kernel.
+///
+/// ```ignore
+/// let ring = IoUring::builder()
+///     .entries(256)
+///     .cq_size(1024)
+///     .single_issuer()
+///     .defer_taskrun()
+///     .build()?;
+/// ```
+pub struct IoUringBuilder<'a> {
+    entries: u32,
+    params: io_uring_params,
+    auto_flush: bool,
+    // The ring passed to `attach_wq`.
+    wq: Option<BorrowedFd<'a>>,
+}
+
+impl Default for IoUringBuilder<'_> {
+    fn default() -> Self {
+        IoUringBuilder {
+            entries: DEFAULT_ENTRIES,
+            params: Default::default(),
+            auto_flush: false,
+            wq: None,
+        }
+    }
+}
+
+impl<'a> IoUringBuilder<'a> {
+    /// Number of SQ entries. The kernel rounds this up to a power of 2.
+    pub fn entries(mut self, entries: u32) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    /// Have a kernel thread poll the SQ, so that submission does not
+    /// require a syscall. The thread goes to sleep after `idle` without
+    /// any submissions.
+    pub fn sq_poll(mut self, idle: Duration) -> Self {
+        self.params.flags |= IORING_SETUP_SQPOLL;
+        self.params.sq_thread_idle = idle.as_millis().min(u32::MAX as u128) as u32;
+        self
+    }
+
+    /// Pin the SQ poll thread to `cpu`. This requires `sq_poll`, without
+    /// it `build` fails with `EINVAL`.
+    pub fn sq_thread_cpu(mut self, cpu: u32) -> Self {
+        self.params.flags |= IORING_SETUP_SQ_AFF;
+        self.params.sq_thread_cpu = cpu;
+        self
+    }
+
+    /// Share the async worker pool of `ring`, and with `sq_poll` its SQ poll
+    /// thread too, rather than starting new ones. `ring` is borrowed until
+    /// `build`, but need not outlive the new ring.
+    pub fn attach_wq(mut self, ring: &'a IoUring) -> Self {
+        self.params.flags |= IORING_SETUP_ATTACH_WQ;
+        self.wq = Some(ring.as_fd());
+        self
+    }
+
+    /// Use a CQ with `entries` entries rather than the default (twice the
+    /// SQ size).
+    pub fn cq_size(mut self, entries: u32) -> Self {
+        self.params.flags |= IORING_SETUP_CQSIZE;
+        self.params.cq_entries = entries;
+        self
+    }
+
+    /// Clamp SQ and CQ sizes to the kernel maximum rather than failing.
+    pub fn clamp(mut self) -> Self {
+        self.params.flags |= IORING_SETUP_CLAMP;
+        self
+    }
+
+    /// Promise that only one task will ever submit to this ring.
+    pub fn single_issuer(mut self) -> Self {
+        self.params.flags |= IORING_SETUP_SINGLE_ISSUER;
+        self
+    }
+
+    /// Defer task work until the application waits for completions. This
+    /// requires `single_issuer`.
+    pub fn defer_taskrun(mut self) -> Self {
+        self.params.flags |= IORING_SETUP_DEFER_TASKRUN;
+        self
+    }
+
+    /// Do not interrupt the application to run task work, instead run it
+    /// the next time the application enters the kernel.
+    pub fn coop_taskrun(mut self) -> Self {
+        self.params.flags |= IORING_SETUP_COOP_TASKRUN;
+        self
+    }
+
+    /// Use 128 byte SQEs.
+    pub fn sqe128(mut self) -> Self {
+        self.params.flags |= IORING_SETUP_SQE128;
+        self
+    }
+
+    /// Use 32 byte CQEs.
+    pub fn cqe32(mut self) -> Self {
+        self.params.flags |= IORING_SETUP_CQE32;
+        self
+    }
+
+    /// Have `io_uring_peek_cqe`, and so every wait, flush CQEs that
+    /// overflowed into the CQ once it has been drained, rather than leaving
+    /// this to [`IoUring::flush_overflow`]. Flushes are counted in
+    /// [`IoUring::cq_overflow`].
+    pub fn auto_flush_overflow(mut self) -> Self {
+        self.auto_flush = true;
+        self
+    }
+
+    /// Create the ring. On success the kernel has filled in the actual
+    /// sizes and supported features, see [`IoUring::params`].
+    pub fn build(self) -> std::io::Result<IoUring> {
+        let mut ring = Default::default();
+        let mut params = self.params;
+        if let Some(wq) = self.wq {
+            params.wq_fd = wq.as_raw_fd() as u32;
+        }
+        let ret = unsafe { io_uring_queue_init_params(self.entries, &mut ring, &mut params) };
+        if ret < 0 {
+            return Err(std::io::Error::from_raw_os_error(-ret));
+        }
+        let handle = match RingHandle::new(ring.ring_fd) {
+            Ok(handle) => handle,
+            Err(e) => {
+                unsafe { io_uring_queue_exit(&mut ring) };
+                return Err(e);
+            }
+        };
+        let sqe_args = (0..ring.sq.ring_entries)
+            .map(|_| Default::default())
+            .collect();
+        Ok(IoUring {
+            ring,
+            params,
+            handle,
+            sqe_args,
+            auto_flush: self.auto_flush,
+            overflow_flushes: 0,
+            _pin: Default::default(),
+        })
+    }
+}
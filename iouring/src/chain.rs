@@ -0,0 +1,130 @@
+use super::*;
+
+/// The user data of a chain SQE that was handed out but not prepared. Its
+/// top bit is set, so it is not a [`Token`] of a `Registry`.
+pub const CHAIN_NOP_USER_DATA: u64 = u64::MAX - 1;
+
+/// SQEs reserved with [`IoUring::chain`], which are linked in the order
+/// they are handed out by [`SqeChain::next_sqe`] once the chain is finished.
+/// Since all SQEs are reserved up front, building a chain cannot fail
+/// halfway for lack of SQEs.
+///
+/// ```ignore
+/// let mut buf = pool.lease().unwrap();
+/// let mut chain = ring.chain(3).unwrap();
+/// let sqe = chain.next_sqe().unwrap();
+/// unsafe { sqe.io_uring_prep_read_fixed(src, &mut buf, off) }.finalize();
+/// let sqe = chain.next_sqe().unwrap();
+/// unsafe { sqe.io_uring_prep_write_fixed(dst, &buf, off) }.finalize();
+/// chain.next_sqe().unwrap().io_uring_prep_fsync(dst, 0).finalize();
+/// chain.finish();
+/// ring.submit();
+/// // The kernel uses `buf` until the chain completes, so it may only be
+/// // dropped or reused once all three CQEs are reaped.
+/// io_uring_wait_cqe_nr(&mut ring, 3)?.unwrap().consume_all();
+/// drop(buf);
+/// ```
+///
+/// Dropping the chain without calling [`SqeChain::finish`] returns all of
+/// its SQEs to the SQ, so none of them are submitted.
+pub struct SqeChain<'a> {
+    ring: &'a mut IoUring,
+    // SQ position of the first SQE.
+    start: u32,
+    len: u32,
+    // Number of SQEs handed out so far.
+    used: u32,
+    hard: bool,
+}
+
+impl SqeChain<'_> {
+    pub(crate) fn new(ring: &mut IoUring, start: u32, len: u32) -> SqeChain<'_> {
+        SqeChain {
+            ring,
+            start,
+            len,
+            used: 0,
+            hard: false,
+        }
+    }
+
+    /// Use hard links, so that the chain is not broken by a request that
+    /// fails, e.g., a short read.
+    pub fn hard_link(mut self) -> Self {
+        self.hard = true;
+        self
+    }
+
+    /// Returns the next SQE of the chain, or `None` once all reserved SQEs
+    /// have been handed out. The SQE starts out as a NOP with
+    /// `CHAIN_NOP_USER_DATA`, so one that is not prepared does no harm. With
+    /// `IORING_FEAT_CQE_SKIP` the NOP posts no CQE either.
+    pub fn next_sqe(&mut self) -> Option<Sqe<'_>> {
+        if self.used == self.len {
+            return None;
+        }
+        let tail = self.start.wrapping_add(self.used);
+        self.used += 1;
+        let skip_cqe = self.ring.params.features & IORING_FEAT_CQE_SKIP != 0;
+        let sqe = unsafe { self.ring.sqe_at(tail) };
+        let sqe = sqe.io_uring_prep_nop().set_sqe_data(CHAIN_NOP_USER_DATA);
+        Some(if skip_cqe { sqe.set_no_cqe() } else { sqe })
+    }
+
+    /// Returns the number of SQEs not yet handed out.
+    pub fn remaining(&self) -> u32 {
+        self.len - self.used
+    }
+
+    /// Link the SQEs handed out, which are then submitted with the next
+    /// submission. Reserved SQEs that were not handed out are returned to
+    /// the SQ.
+    pub fn finish(self) {
+        let link = if self.hard {
+            1u8 << IOSQE_IO_HARDLINK_BIT
+        } else {
+            1u8 << IOSQE_IO_LINK_BIT
+        };
+        let unlink = !(1u8 << IOSQE_IO_LINK_BIT | 1u8 << IOSQE_IO_HARDLINK_BIT);
+        for i in 0..self.used {
+            let sqe = unsafe { &mut *self.ring.sqe_at(self.start.wrapping_add(i)).get_sqe() };
+            // The last SQE must not link to whatever is queued after it.
+            if i + 1 < self.used {
+                sqe.flags |= link;
+            } else {
+                sqe.flags &= unlink;
+            }
+        }
+        // Only the SQEs beyond `used` are returned.
+        self.ring.ring.sq.sqe_tail = self.start.wrapping_add(self.used);
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for SqeChain<'_> {
+    fn drop(&mut self) {
+        // Nothing can have been queued after the chain, since it borrows the
+        // ring.
+        self.ring.ring.sq.sqe_tail = self.start;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeRing;
+
+    #[test]
+    fn placeholder_nop() {
+        for features in [0, IORING_FEAT_CQE_SKIP] {
+            let mut ring = FakeRing::new(4, 0);
+            ring.params.features = features;
+            let mut chain = ring.chain(2).unwrap();
+            let sqe = unsafe { &*chain.next_sqe().unwrap().get_sqe() };
+            assert_eq!(sqe.opcode, IORING_OP_NOP as u8);
+            assert_eq!(sqe.user_data, CHAIN_NOP_USER_DATA);
+            let skip = sqe.flags & (1 << IOSQE_CQE_SKIP_SUCCESS_BIT) != 0;
+            assert_eq!(skip, features != 0);
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use std::marker::PhantomData;
+use std::ptr::null;
+use std::time::Duration;
 
 use super::*;
 
@@ -15,6 +17,103 @@ impl io_uring_cqe {
     pub fn get_result(&self) -> i32 {
         self.res
     }
+
+    /// Returns the result, mapping negative values to the corresponding
+    /// `io::Error`.
+    pub fn result(&self) -> std::io::Result<u32> {
+        if self.res < 0 {
+            Err(std::io::Error::from_raw_os_error(-self.res))
+        } else {
+            Ok(self.res as u32)
+        }
+    }
+
+    /// For poll requests, returns the events that fired.
+    pub fn poll_events(&self) -> std::io::Result<PollEvents> {
+        self.result().map(PollEvents::from_bits)
+    }
+
+    /// Returns the ID of the buffer the kernel picked for this request
+    /// when buffer selection was used, otherwise `None`.
+    pub fn buffer_id(&self) -> Option<u16> {
+        if self.flags & IORING_CQE_F_BUFFER != 0 {
+            Some((self.flags >> IORING_CQE_BUFFER_SHIFT) as u16)
+        } else {
+            None
+        }
+    }
+
+    /// For socket receives, whether the socket still had data available
+    /// when the request completed.
+    pub fn sock_nonempty(&self) -> bool {
+        self.flags & IORING_CQE_F_SOCK_NONEMPTY != 0
+    }
+
+    /// For zero-copy sends, whether this is the notification that the
+    /// kernel is done with the buffer, rather than the result of the send.
+    pub fn is_notification(&self) -> bool {
+        self.flags & IORING_CQE_F_NOTIF != 0
+    }
+}
+
+/// Counts of CQ overflows, returned by [`IoUring::cq_overflow`]. The CQ
+/// overflows when completions arrive faster than they are reaped, e.g., with
+/// a burst of connections on a multishot accept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CqOverflow {
+    /// Times the overflowed CQEs were flushed by `io_uring_peek_cqe`, see
+    /// `IoUringBuilder::auto_flush_overflow`.
+    pub flushes: u64,
+    /// CQEs the kernel dropped, which are lost. Without
+    /// `IORING_FEAT_NODROP` this happens whenever the CQ is full, otherwise
+    /// only if the kernel cannot allocate memory for the overflow backlog.
+    /// Wraps around.
+    pub dropped: u32,
+}
+
+/// User data of the timeout SQEs used to bound waits on kernels without
+/// `IORING_FEAT_EXT_ARG`. Their CQEs are consumed by `io_uring_peek_cqe`.
+pub const LIBURING_UDATA_TIMEOUT: u64 = u64::MAX;
+
+// The size of the kernel's `sigset_t`, which is smaller than libc's.
+const KERNEL_SIGSET_SIZE: u32 = 8;
+
+/// A CQE from a ring set up with `IORING_SETUP_CQE32`, which is followed by
+/// 16 bytes of extra data, e.g., for `IORING_OP_URING_CMD`. Derefs to the
+/// normal CQE fields.
+pub struct BigCqe<'a> {
+    cqe: *const io_uring_cqe,
+    _life: PhantomData<&'a ()>,
+}
+
+impl BigCqe<'_> {
+    /// Returns the extra data.
+    pub fn big_cqe(&self) -> &[u64; 2] {
+        unsafe {
+            &*(self.cqe as *const u8)
+                .add(std::mem::size_of::<io_uring_cqe>())
+                .cast()
+        }
+    }
+}
+
+impl std::ops::Deref for BigCqe<'_> {
+    type Target = io_uring_cqe;
+
+    fn deref(&self) -> &io_uring_cqe {
+        unsafe { &*self.cqe }
+    }
+}
+
+/// The index of a CQE in `cq.cqes` is shifted by this, as big CQEs take up
+/// two `io_uring_cqe`s.
+#[inline(always)]
+fn io_uring_cqe_shift(ring: &io_uring) -> u32 {
+    if ring.flags & IORING_SETUP_CQE32 != 0 {
+        1
+    } else {
+        0
+    }
 }
 
 #[inline(always)]
@@ -30,17 +129,65 @@ unsafe fn io_uring_cqe_seen(ring: &mut io_uring) {
     io_uring_cq_advance(ring, 1)
 }
 
+/// An owned copy of a CQE, which unlike the `io_uring_cqe`s in the ring
+/// stays valid once the CQE is consumed. Derefs to `io_uring_cqe`, so has
+/// the same accessors.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cqe {
+    pub user_data: u64,
+    pub res: i32,
+    pub flags: u32,
+    /// Extra data of big CQEs, zero unless the ring was set up with
+    /// `IORING_SETUP_CQE32`.
+    pub big_cqe: [u64; 2],
+}
+
+impl Cqe {
+    /// # Safety
+    /// `cqe` must point to a valid CQE, followed by its extra data if `big`.
+    unsafe fn read(cqe: *const io_uring_cqe, big: bool) -> Cqe {
+        let cqe = &*cqe;
+        Cqe {
+            user_data: cqe.user_data,
+            res: cqe.res,
+            flags: cqe.flags,
+            big_cqe: if big {
+                *(cqe as *const io_uring_cqe).add(1).cast::<[u64; 2]>()
+            } else {
+                [0; 2]
+            },
+        }
+    }
+}
+
+impl std::ops::Deref for Cqe {
+    type Target = io_uring_cqe;
+
+    fn deref(&self) -> &io_uring_cqe {
+        // `Cqe` starts with the same fields as `io_uring_cqe`.
+        unsafe { &*(self as *const Cqe).cast() }
+    }
+}
+
 /// A holder for a set of CQEs, potentially
 /// returned by peek or get.
+///
+/// CQEs are indexed from the first one not yet consumed, and iterating
+/// over the jar consumes each CQE as it is returned.
 pub struct CqeJar<'a> {
     ring: NonNull<io_uring>,
-    cqes: *mut io_uring_cqe,
+    // CQ head when the jar was created, CQE `i` of the jar is at
+    // `head + i`, wrapping around the end of the ring.
+    head: u32,
     // First valid CQE, we need this because
     // accessing CQEs after advancing is not
     // safe.
     begin: isize,
     // Bounds
     end: isize,
+    // See `io_uring_cqe_shift`.
+    shift: u32,
     _life: PhantomData<&'a ()>,
 }
 
@@ -48,20 +195,21 @@ impl CqeJar<'_> {
     /// Create a CqeJar.
     ///
     /// # Safety
-    /// This function cannot check that the CQEs and length are
+    /// This function cannot check that the CQ head and length are
     /// correct, and instead assumes this is the case. This is
     /// of course unsafe, since it allows access to arbitrary
     /// memory.
     pub(self) unsafe fn init<'a>(
-        cqes: *mut io_uring_cqe,
+        head: u32,
         available: isize,
         ring: NonNull<io_uring>,
     ) -> CqeJar<'a> {
         CqeJar {
             ring,
-            cqes,
+            head,
             begin: 0,
             end: available,
+            shift: io_uring_cqe_shift(ring.as_ref()),
             _life: Default::default(),
         }
     }
@@ -69,8 +217,22 @@ impl CqeJar<'_> {
     /// Get CQE at `idx` if available otherwise return `None`.
     #[inline(always)]
     pub fn peek(&self, idx: isize) -> Option<&io_uring_cqe> {
-        if idx >= self.begin && idx < self.end {
-            unsafe { Some(&*self.cqes.offset(idx)) }
+        if idx >= 0 && idx < self.available() {
+            unsafe { Some(&*self.cqe_ptr(self.begin + idx)) }
+        } else {
+            None
+        }
+    }
+
+    /// Get CQE at `idx` along with its extra data if available, otherwise
+    /// return `None`. Always `None` unless the ring was set up with
+    /// `IORING_SETUP_CQE32`.
+    pub fn peek_big(&self, idx: isize) -> Option<BigCqe<'_>> {
+        if self.shift != 0 && idx >= 0 && idx < self.available() {
+            Some(BigCqe {
+                cqe: self.cqe_ptr(self.begin + idx),
+                _life: PhantomData,
+            })
         } else {
             None
         }
@@ -79,19 +241,55 @@ impl CqeJar<'_> {
     /// Get CQE at `idx` if available otherwise return `None`.
     #[inline(always)]
     pub fn peek_mut(&mut self, idx: isize) -> Option<&mut io_uring_cqe> {
-        if idx < self.end - self.begin {
-            unsafe { Some(&mut *self.cqes.offset(idx + self.begin)) }
+        if idx >= 0 && idx < self.available() {
+            unsafe { Some(&mut *self.cqe_ptr(self.begin + idx)) }
         } else {
             None
         }
     }
 
+    /// Returns the CQE at `pos`, counting from the jar's first CQE
+    /// regardless of how many were consumed.
+    #[inline(always)]
+    fn cqe_ptr(&self, pos: isize) -> *mut io_uring_cqe {
+        unsafe {
+            let cq = &self.ring.as_ref().cq;
+            let idx = self.head.wrapping_add(pos as u32) & cq.ring_mask;
+            cq.cqes.add((idx << self.shift) as usize)
+        }
+    }
+
+    /// Call `f` on each CQE, then consume them all at once. Returns the
+    /// number of CQEs seen.
+    pub fn for_each_cqe(&mut self, mut f: impl FnMut(&io_uring_cqe)) -> usize {
+        let count = self.available();
+        for pos in self.begin..self.end {
+            f(unsafe { &*self.cqe_ptr(pos) });
+        }
+        self.consume_all();
+        count as usize
+    }
+
+    /// Copy as many CQEs as fit into `cqes` and consume them, leaving the
+    /// rest in the jar. Returns the number of CQEs copied.
+    pub fn copy_cqes(&mut self, cqes: &mut [Cqe]) -> usize {
+        let count = cqes.len().min(self.available() as usize);
+        for (i, cqe) in cqes[..count].iter_mut().enumerate() {
+            *cqe = unsafe { Cqe::read(self.cqe_ptr(self.begin + i as isize), self.shift != 0) };
+        }
+        unsafe { io_uring_cq_advance(self.ring.as_mut(), count as u32) };
+        self.begin += count as isize;
+        count
+    }
+
     /// Consume one CQE, i.e., return it to the kernel. The CQE
     /// content cannot be trusted at this point.
     #[inline(always)]
     pub fn consume_one(&mut self) {
-        unsafe { io_uring_cqe_seen(self.ring.as_mut()) };
-        self.begin += 1;
+        if self.available() > 0 {
+            unsafe { io_uring_cqe_seen(self.ring.as_mut()) };
+            self.begin += 1;
+        }
     }
 
     /// Consume all CQEs, i.e., return them to the kernel. No
@@ -99,8 +297,7 @@ impl CqeJar<'_> {
     #[inline(always)]
     pub fn consume_all(&mut self) {
         unsafe { io_uring_cq_advance(self.ring.as_mut(), self.available() as u32) };
-        self.begin = 0;
-        self.end = 0;
+        self.begin = self.end;
     }
 
     /// Return the number of CQEs available.
@@ -109,6 +306,27 @@ impl CqeJar<'_> {
     }
 }
 
+impl Iterator for CqeJar<'_> {
+    type Item = Cqe;
+
+    /// Returns a copy of the next CQE, consuming it.
+    fn next(&mut self) -> Option<Cqe> {
+        if self.available() == 0 {
+            return None;
+        }
+        let cqe = unsafe { Cqe::read(self.cqe_ptr(self.begin), self.shift != 0) };
+        self.consume_one();
+        Some(cqe)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.available() as usize;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for CqeJar<'_> {}
+
 impl Drop for CqeJar<'_> {
     fn drop(&mut self) {
         self.consume_all()
@@ -118,30 +336,26 @@ impl Drop for CqeJar<'_> {
 pub unsafe fn io_uring_peek_cqe<'a>(
     ring: &'a mut IoUring,
 ) -> Result<Option<CqeJar<'a>>, std::io::Error> {
-    let shift = if ring.ring.flags & IORING_SETUP_CQE32 != 0 {
-        0
-    } else {
-        1
-    };
+    let shift = io_uring_cqe_shift(&ring.ring);
     let cqtail = AtomicU32::from_mut(&mut *ring.ring.cq.ktail);
     let cqhead = AtomicU32::from_mut(&mut *ring.ring.cq.khead);
-    const LIBURING_UDATA_TIMEOUT: u64 = u64::MAX;
     let mut err = 0;
     loop {
         let tail = cqtail.load(Ordering::Acquire);
         let head = cqhead.load(Ordering::Relaxed);
-        let available = tail - head;
+        let available = tail.wrapping_sub(head);
         if available > 0 {
             let cqes = ring
                 .ring
                 .cq
                 .cqes
-                .offset(((head & ring.ring.cq.ring_mask) >> shift) as isize);
-            // Timeout handling, consume cqes that indicate timeouts
+                .offset(((head & ring.ring.cq.ring_mask) << shift) as isize);
+            // Timeout handling, consume cqes that indicate timeouts. Expiry is
+            // the expected way for these to complete, so is not an error.
             if ring.ring.features & IORING_FEAT_EXT_ARG == 0
                 && (*cqes).user_data == LIBURING_UDATA_TIMEOUT
             {
-                if (*cqes).res < 0 {
+                if (*cqes).res < 0 && (*cqes).res != -libc::ETIME {
                     err = (*cqes).res
                 };
                 io_uring_cq_advance(&mut ring.ring, 1);
@@ -152,11 +366,16 @@ pub unsafe fn io_uring_peek_cqe<'a>(
                 }
             } else {
                 return Ok(Some(CqeJar::init(
-                    cqes,
+                    head,
                     available as isize,
                     (&mut ring.ring).into(),
                 )));
             }
+        } else if ring.auto_flush && ring.flush_overflow()? {
+            ring.overflow_flushes += 1;
+            if ring.io_uring_cq_ready() == 0 {
+                return Ok(None);
+            }
         } else {
             return Ok(None);
         }
@@ -173,9 +392,7 @@ pub fn io_uring_wait_cqe_nr<'a>(
         let mut cqe_ptr: *mut io_uring_cqe = null_mut();
         let ret = unsafe { __io_uring_get_cqe(&mut ring.ring, &mut cqe_ptr, 0, nr, null_mut()) };
         if ret == 0 {
-            Ok(Some(unsafe {
-                CqeJar::init(cqe_ptr, nr as isize, (&mut ring.ring).into())
-            }))
+            unsafe { io_uring_peek_cqe(ring) }
         } else if ret < 0 {
             Err(std::io::Error::from_raw_os_error(-ret))
         } else {
@@ -184,6 +401,167 @@ pub fn io_uring_wait_cqe_nr<'a>(
     }
 }
 
+/// Submit pending SQEs and wait for at least `nr` CQEs, but no longer than
+/// `ts`. Signals in `sigmask`, if given, are blocked only while waiting, as
+/// with `pselect`. Returns whatever CQEs are available when the wait ends,
+/// which may be none if it timed out.
+///
+/// On kernels without `IORING_FEAT_EXT_ARG` this queues a timeout SQE with
+/// `LIBURING_UDATA_TIMEOUT` as its user data, so needs a free SQE.
+pub fn io_uring_submit_and_wait_timeout<'a>(
+    ring: &'a mut IoUring,
+    nr: u32,
+    ts: Duration,
+    sigmask: Option<&libc::sigset_t>,
+) -> Result<Option<CqeJar<'a>>, std::io::Error> {
+    let sigmask = sigmask.map_or(null(), |mask| mask as *const libc::sigset_t);
+    if ring.ring.features & IORING_FEAT_EXT_ARG != 0 {
+        let ret = unsafe { io_uring_submit(&mut ring.ring) };
+        if ret < 0 {
+            return Err(std::io::Error::from_raw_os_error(-ret));
+        }
+        if ring.io_uring_cq_ready() < nr {
+            let ts = __kernel_timespec {
+                tv_sec: ts.as_secs() as _,
+                tv_nsec: ts.subsec_nanos() as _,
+            };
+            let arg = io_uring_getevents_arg {
+                sigmask: sigmask as u64,
+                sigmask_sz: KERNEL_SIGSET_SIZE,
+                pad: 0,
+                ts: &ts as *const __kernel_timespec as u64,
+            };
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    ring.ring.ring_fd,
+                    0,
+                    nr,
+                    IORING_ENTER_GETEVENTS | IORING_ENTER_EXT_ARG,
+                    &arg as *const io_uring_getevents_arg,
+                    std::mem::size_of::<io_uring_getevents_arg>(),
+                )
+            };
+            if ret < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::ETIME) {
+                    return Err(err);
+                }
+            }
+        }
+    } else {
+        if ring.io_uring_sq_available() == 0 {
+            ring.submit();
+        }
+        let Some(sqe) = ring.io_uring_get_sqe() else {
+            return Err(std::io::Error::from_raw_os_error(libc::EBUSY));
+        };
+        sqe.io_uring_prep_timeout(ts, nr, 0)
+            .set_sqe_data(LIBURING_UDATA_TIMEOUT)
+            .finalize();
+        let ret = unsafe { io_uring_submit(&mut ring.ring) };
+        if ret < 0 {
+            return Err(std::io::Error::from_raw_os_error(-ret));
+        }
+        let mut cqe_ptr: *mut io_uring_cqe = null_mut();
+        let ret =
+            unsafe { __io_uring_get_cqe(&mut ring.ring, &mut cqe_ptr, 0, nr, sigmask as *mut _) };
+        if ret < 0 && ret != -libc::ETIME {
+            return Err(std::io::Error::from_raw_os_error(-ret));
+        }
+    }
+    unsafe { io_uring_peek_cqe(ring) }
+}
+
 pub fn io_uring_wait_cqe(ring: &mut IoUring) -> Result<Option<CqeJar>, std::io::Error> {
     io_uring_wait_cqe_nr(ring, 1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRIES: usize = 4;
+
+    // A CQ of `ENTRIES` entries, with its CQEs in `mem`. CQE `i` has `i` as
+    // its user data and, if `big`, `[i + 100, i + 200]` as its extra data.
+    fn cq(mem: &mut Vec<u64>, khead: &mut u32, big: bool) -> io_uring {
+        let words = if big { 4 } else { 2 };
+        *mem = vec![0; ENTRIES * words];
+        for i in 0..ENTRIES {
+            mem[i * words] = i as u64;
+            if big {
+                mem[i * words + 2] = i as u64 + 100;
+                mem[i * words + 3] = i as u64 + 200;
+            }
+        }
+        let mut ring: io_uring = unsafe { std::mem::zeroed() };
+        ring.flags = if big { IORING_SETUP_CQE32 } else { 0 };
+        ring.cq.cqes = mem.as_mut_ptr().cast();
+        ring.cq.khead = khead;
+        ring.cq.ring_mask = ENTRIES as u32 - 1;
+        ring.cq.ring_entries = ENTRIES as u32;
+        ring
+    }
+
+    #[test]
+    fn cqe_shift() {
+        let mut ring: io_uring = unsafe { std::mem::zeroed() };
+        assert_eq!(io_uring_cqe_shift(&ring), 0);
+        ring.flags = IORING_SETUP_CQE32;
+        assert_eq!(io_uring_cqe_shift(&ring), 1);
+    }
+
+    #[test]
+    fn peek_indexes_both_layouts() {
+        for big in [false, true] {
+            let (mut mem, mut khead) = (Vec::new(), 0);
+            let mut ring = cq(&mut mem, &mut khead, big);
+            let jar = unsafe { CqeJar::init(0, ENTRIES as isize, NonNull::from(&mut ring)) };
+            for i in 0..ENTRIES {
+                assert_eq!(jar.peek(i as isize).unwrap().user_data, i as u64);
+            }
+            assert!(jar.peek(ENTRIES as isize).is_none());
+            assert!(jar.peek(-1).is_none());
+        }
+    }
+
+    #[test]
+    fn big_cqe() {
+        let (mut mem, mut khead) = (Vec::new(), 0);
+        let mut ring = cq(&mut mem, &mut khead, true);
+        let mut jar = unsafe { CqeJar::init(0, ENTRIES as isize, NonNull::from(&mut ring)) };
+        for i in 0..ENTRIES as u64 {
+            let cqe = jar.peek_big(i as isize).unwrap();
+            assert_eq!(cqe.user_data, i);
+            assert_eq!(cqe.big_cqe(), &[i + 100, i + 200]);
+        }
+        let cqe = jar.next().unwrap();
+        assert_eq!((cqe.user_data, cqe.big_cqe), (0, [100, 200]));
+
+        let (mut mem, mut khead) = (Vec::new(), 0);
+        let mut ring = cq(&mut mem, &mut khead, false);
+        let mut jar = unsafe { CqeJar::init(0, ENTRIES as isize, NonNull::from(&mut ring)) };
+        assert!(jar.peek_big(0).is_none());
+        assert_eq!(jar.next().unwrap().big_cqe, [0, 0]);
+    }
+
+    #[test]
+    fn wraps_around_ring_end() {
+        for big in [false, true] {
+            let (mut mem, mut khead) = (Vec::new(), 3);
+            let mut ring = cq(&mut mem, &mut khead, big);
+            {
+                let mut jar = unsafe { CqeJar::init(3, 2, NonNull::from(&mut ring)) };
+                assert_eq!(jar.peek(0).unwrap().user_data, 3);
+                assert_eq!(jar.peek(1).unwrap().user_data, 0);
+                jar.consume_one();
+                assert_eq!(jar.available(), 1);
+                assert_eq!(jar.peek(0).unwrap().user_data, 0);
+                let rest: Vec<u64> = jar.map(|cqe| cqe.user_data).collect();
+                assert_eq!(rest, [0]);
+            }
+            assert_eq!(khead, 5);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{null, NonNull};
+use std::rc::Rc;
+
+use super::*;
+
+// A buffer allocation, kept to free it with the same layout.
+type Alloc = (NonNull<u8>, Layout);
+
+struct PoolInner {
+    handle: RingHandle,
+    // Registered buffers indexed by `buf_index`, `None` for empty slots.
+    bufs: RefCell<Vec<Option<Alloc>>>,
+    // Registered buffers that are not leased.
+    free: RefCell<Vec<u16>>,
+}
+
+impl Drop for PoolInner {
+    fn drop(&mut self) {
+        unsafe {
+            // Every lease has been dropped, and leases outlive the requests
+            // using them (see the `*_fixed` preps), so the kernel is done
+            // with the buffers. Unregistering does not wait for requests.
+            let _ = self.handle.register(IORING_UNREGISTER_BUFFERS, null(), 0);
+            for (ptr, layout) in self.bufs.get_mut().drain(..).flatten() {
+                dealloc(ptr.as_ptr(), layout);
+            }
+        }
+    }
+}
+
+/// A pool of buffers registered with an `IoUring`, for use with the
+/// `*_fixed` operations. Registered buffers are pinned and mapped by the
+/// kernel once, rather than on every request.
+///
+/// The buffers stay registered until the pool and all `FixedBuf`s leased
+/// from it are dropped.
+pub struct FixedBufPool {
+    inner: Rc<PoolInner>,
+}
+
+/// A buffer leased from a `FixedBufPool`, which is returned to the pool when
+/// dropped. Dereferences to the first `len` bytes of the buffer.
+///
+/// A lease passed to a `*_fixed` prep must be kept until the request
+/// completes, otherwise the kernel may be accessing it after it has been
+/// leased again. `op::ReadFixed` and `op::WriteFixed` take care of this.
+pub struct FixedBuf {
+    inner: Rc<PoolInner>,
+    index: u16,
+    ptr: NonNull<u8>,
+    cap: usize,
+    len: usize,
+}
+
+impl FixedBuf {
+    /// Returns the index the buffer is registered at.
+    pub fn buf_index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Set the number of valid bytes, e.g., to the result of a read.
+    ///
+    /// # Panics
+    /// If `len` exceeds the capacity.
+    pub fn set_len(&mut self, len: usize) {
+        assert!(len <= self.cap, "length {} exceeds buffer capacity", len);
+        self.len = len;
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+}
+
+impl Deref for FixedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for FixedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for FixedBuf {
+    fn drop(&mut self) {
+        self.inner.free.borrow_mut().push(self.index);
+    }
+}
+
+/// Allocate `count` zeroed, page aligned buffers of `size` bytes each.
+fn alloc_buffers(count: u16, size: usize) -> std::io::Result<Vec<Alloc>> {
+    if size == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "buffer size must not be zero",
+        ));
+    }
+    let layout = Layout::from_size_align(size, 4096)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut bufs = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        match NonNull::new(unsafe { alloc_zeroed(layout) }) {
+            Some(ptr) => bufs.push((ptr, layout)),
+            None => {
+                for (ptr, layout) in bufs {
+                    unsafe { dealloc(ptr.as_ptr(), layout) };
+                }
+                return Err(std::io::ErrorKind::OutOfMemory.into());
+            }
+        }
+    }
+    Ok(bufs)
+}
+
+fn iovecs(bufs: &[Alloc]) -> Vec<libc::iovec> {
+    bufs.iter()
+        .map(|(ptr, layout)| libc::iovec {
+            iov_base: ptr.as_ptr() as *mut libc::c_void,
+            iov_len: layout.size(),
+        })
+        .collect()
+}
+
+impl FixedBufPool {
+    fn register(
+        ring: &IoUring,
+        nr: u16,
+        flags: u32,
+        data: *const libc::iovec,
+    ) -> std::io::Result<FixedBufPool> {
+        let reg = io_uring_rsrc_register {
+            nr: nr as u32,
+            flags,
+            data: data as u64,
+            ..Default::default()
+        };
+        unsafe {
+            ring.handle.register(
+                IORING_REGISTER_BUFFERS2,
+                &reg as *const io_uring_rsrc_register as *const libc::c_void,
+                std::mem::size_of::<io_uring_rsrc_register>() as u32,
+            )
+        }?;
+        Ok(FixedBufPool {
+            inner: Rc::new(PoolInner {
+                handle: ring.handle.clone(),
+                bufs: RefCell::new(vec![None; nr as usize]),
+                free: RefCell::new(Vec::new()),
+            }),
+        })
+    }
+
+    /// Allocate and register `count` buffers of `size` bytes each.
+    pub fn new(ring: &mut IoUring, count: u16, size: usize) -> std::io::Result<FixedBufPool> {
+        let bufs = alloc_buffers(count, size)?;
+        let pool = match Self::register(ring, count, 0, iovecs(&bufs).as_ptr()) {
+            Ok(pool) => pool,
+            Err(e) => {
+                for (ptr, layout) in bufs {
+                    unsafe { dealloc(ptr.as_ptr(), layout) };
+                }
+                return Err(e);
+            }
+        };
+        *pool.inner.bufs.borrow_mut() = bufs.into_iter().map(Some).collect();
+        pool.inner.free.borrow_mut().extend((0..count).rev());
+        Ok(pool)
+    }
+
+    /// Register an empty table with room for `nr` buffers, which can be
+    /// filled later with `add_buffers`.
+    pub fn new_sparse(ring: &mut IoUring, nr: u16) -> std::io::Result<FixedBufPool> {
+        Self::register(ring, nr, IORING_RSRC_REGISTER_SPARSE, null())
+    }
+
+    /// Allocate `count` buffers of `size` bytes each and register them in
+    /// the first empty slots of the table.
+    pub fn add_buffers(&self, count: u16, size: usize) -> std::io::Result<()> {
+        let empty: Vec<u16> = self
+            .inner
+            .bufs
+            .borrow()
+            .iter()
+            .enumerate()
+            .filter(|(_, buf)| buf.is_none())
+            .map(|(i, _)| i as u16)
+            .take(count as usize)
+            .collect();
+        if empty.len() < count as usize {
+            return Err(std::io::Error::from_raw_os_error(libc::ENOSPC));
+        }
+        let bufs = alloc_buffers(count, size)?;
+        let iovs = iovecs(&bufs);
+        // Slots need not be contiguous, so register each separately.
+        for (i, (slot, iov)) in empty.iter().zip(&iovs).enumerate() {
+            let update = io_uring_rsrc_update2 {
+                offset: *slot as u32,
+                data: iov as *const libc::iovec as u64,
+                nr: 1,
+                ..Default::default()
+            };
+            let ret = unsafe {
+                self.inner.handle.register(
+                    IORING_REGISTER_BUFFERS_UPDATE,
+                    &update as *const io_uring_rsrc_update2 as *const libc::c_void,
+                    std::mem::size_of::<io_uring_rsrc_update2>() as u32,
+                )
+            };
+            if let Err(e) = ret {
+                for (ptr, layout) in &bufs[i..] {
+                    unsafe { dealloc(ptr.as_ptr(), *layout) };
+                }
+                return Err(e);
+            }
+            self.inner.bufs.borrow_mut()[*slot as usize] = Some(bufs[i]);
+            self.inner.free.borrow_mut().push(*slot);
+        }
+        Ok(())
+    }
+
+    /// Lease a free buffer, or `None` if all are in use. The lease starts
+    /// out with its length set to its capacity.
+    pub fn lease(&self) -> Option<FixedBuf> {
+        let index = self.inner.free.borrow_mut().pop()?;
+        let (ptr, layout) = self.inner.bufs.borrow()[index as usize]?;
+        Some(FixedBuf {
+            inner: self.inner.clone(),
+            index,
+            ptr,
+            cap: layout.size(),
+            len: layout.size(),
+        })
+    }
+}
@@ -0,0 +1,218 @@
+use std::cell::{Cell, RefCell};
+use std::os::fd::RawFd;
+use std::ptr::null;
+use std::rc::Rc;
+
+use super::*;
+
+struct FilesInner {
+    handle: RingHandle,
+    // Slots holding a file, whether inserted by us or allocated by the kernel.
+    used: RefCell<Vec<bool>>,
+    // Slots held by a `FixedFd`.
+    owned: RefCell<Vec<bool>>,
+    // Slots `[start, end)` the kernel allocates from when asked for
+    // IORING_FILE_INDEX_ALLOC, the whole table until `set_alloc_range`.
+    alloc_range: Cell<(u32, u32)>,
+}
+
+impl FilesInner {
+    fn update(&self, offset: u32, fds: &[RawFd]) -> std::io::Result<u32> {
+        let update = io_uring_files_update {
+            offset,
+            resv: 0,
+            fds: fds.as_ptr() as u64,
+        };
+        let ret = unsafe {
+            self.handle.register(
+                IORING_REGISTER_FILES_UPDATE,
+                &update as *const io_uring_files_update as *const libc::c_void,
+                fds.len() as u32,
+            )
+        }?;
+        let mut used = self.used.borrow_mut();
+        for (i, fd) in fds.iter().enumerate().take(ret as usize) {
+            if *fd != IORING_REGISTER_FILES_SKIP {
+                used[offset as usize + i] = *fd >= 0;
+            }
+        }
+        Ok(ret as u32)
+    }
+}
+
+impl Drop for FilesInner {
+    fn drop(&mut self) {
+        let _ = unsafe { self.handle.register(IORING_UNREGISTER_FILES, null(), 0) };
+    }
+}
+
+/// The fixed file table registered with an `IoUring`. Requests can refer to
+/// a file in the table by its slot (using `Sqe::set_fixed_file`), which saves
+/// the kernel from looking up the FD on every operation.
+///
+/// The table stays registered until it and all `FixedFd`s taken from it are
+/// dropped.
+pub struct FixedFiles {
+    inner: Rc<FilesInner>,
+}
+
+/// A file in the fixed file table. The slot is cleared when this is dropped;
+/// requests already in flight keep their reference to the file.
+pub struct FixedFd {
+    inner: Rc<FilesInner>,
+    slot: u32,
+}
+
+impl FixedFd {
+    /// Returns the slot, which is what is passed as the FD for requests using
+    /// `set_fixed_file`.
+    pub fn slot(&self) -> u32 {
+        self.slot
+    }
+}
+
+impl Drop for FixedFd {
+    fn drop(&mut self) {
+        let _ = self.inner.update(self.slot, &[-1]);
+        self.inner.owned.borrow_mut()[self.slot as usize] = false;
+    }
+}
+
+impl FixedFiles {
+    fn init(ring: &IoUring, nr: u32, reg: io_uring_rsrc_register) -> std::io::Result<FixedFiles> {
+        unsafe {
+            ring.handle.register(
+                IORING_REGISTER_FILES2,
+                &reg as *const io_uring_rsrc_register as *const libc::c_void,
+                std::mem::size_of::<io_uring_rsrc_register>() as u32,
+            )
+        }?;
+        Ok(FixedFiles {
+            inner: Rc::new(FilesInner {
+                handle: ring.handle.clone(),
+                used: RefCell::new(vec![false; nr as usize]),
+                owned: RefCell::new(vec![false; nr as usize]),
+                alloc_range: Cell::new((0, nr)),
+            }),
+        })
+    }
+
+    /// Returns the number of slots in the table.
+    pub fn len(&self) -> u32 {
+        self.inner.used.borrow().len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Restrict the slots the kernel picks from when a request asks for
+    /// `IORING_FILE_INDEX_ALLOC` to `[offset, offset + len)`. `insert` only
+    /// uses slots outside this range.
+    pub fn set_alloc_range(&self, offset: u32, len: u32) -> std::io::Result<()> {
+        let range = io_uring_file_index_range {
+            off: offset,
+            len,
+            resv: 0,
+        };
+        unsafe {
+            self.inner.handle.register(
+                IORING_REGISTER_FILE_ALLOC_RANGE,
+                &range as *const io_uring_file_index_range as *const libc::c_void,
+                0,
+            )
+        }?;
+        self.inner.alloc_range.set((offset, offset + len));
+        Ok(())
+    }
+
+    /// Install a file in a free slot outside the allocation range. The table
+    /// holds its own reference to the file, so `fd` can be closed afterwards.
+    ///
+    /// The kernel allocates from the whole table by default, so that a slot
+    /// `insert` is about to use could be taken by an `accept_direct` in
+    /// flight. `set_alloc_range` must leave slots outside the range first,
+    /// otherwise this fails with `ENFILE`.
+    pub fn insert(&self, fd: RawFd) -> std::io::Result<FixedFd> {
+        let (start, end) = self.inner.alloc_range.get();
+        let slot = self
+            .inner
+            .used
+            .borrow()
+            .iter()
+            .enumerate()
+            .position(|(i, used)| !used && !(start..end).contains(&(i as u32)))
+            .ok_or_else(|| std::io::Error::from_raw_os_error(libc::ENFILE))?;
+        self.inner.update(slot as u32, &[fd])?;
+        self.inner.owned.borrow_mut()[slot] = true;
+        Ok(FixedFd {
+            inner: self.inner.clone(),
+            slot: slot as u32,
+        })
+    }
+
+    /// Take ownership of a slot that was filled by the kernel, e.g., the CQE
+    /// result of an `accept_direct` or `socket_direct` that asked for
+    /// `IORING_FILE_INDEX_ALLOC`, or one of the FDs passed to
+    /// `register_files`.
+    ///
+    /// Returns `None` if `slot` is out of range, or already owned by a
+    /// `FixedFd`.
+    pub fn adopt(&self, slot: u32) -> Option<FixedFd> {
+        let mut owned = self.inner.owned.borrow_mut();
+        let owned = owned.get_mut(slot as usize)?;
+        if *owned {
+            return None;
+        }
+        *owned = true;
+        self.inner.used.borrow_mut()[slot as usize] = true;
+        Some(FixedFd {
+            inner: self.inner.clone(),
+            slot,
+        })
+    }
+
+    /// Replace the files in slots starting at `offset` with `fds`. A FD of -1
+    /// clears a slot, and `IORING_REGISTER_FILES_SKIP` leaves it unchanged.
+    /// Returns the number of slots updated.
+    ///
+    /// This bypasses `FixedFd` ownership, so callers should not use it on
+    /// slots owned by a `FixedFd`.
+    pub fn update(&self, offset: u32, fds: &[RawFd]) -> std::io::Result<u32> {
+        self.inner.update(offset, fds)
+    }
+}
+
+impl IoUring {
+    /// Register `fds` as the fixed file table, with `fds[i]` in slot `i`.
+    /// Use -1 to leave a slot empty. Only one table can be registered with
+    /// a ring at a time.
+    pub fn register_files(&mut self, fds: &[RawFd]) -> std::io::Result<FixedFiles> {
+        let files = FixedFiles::init(
+            self,
+            fds.len() as u32,
+            io_uring_rsrc_register {
+                nr: fds.len() as u32,
+                data: fds.as_ptr() as u64,
+                ..Default::default()
+            },
+        )?;
+        for (used, fd) in files.inner.used.borrow_mut().iter_mut().zip(fds) {
+            *used = *fd >= 0;
+        }
+        Ok(files)
+    }
+
+    /// Register an empty fixed file table with `nr` slots.
+    pub fn register_files_sparse(&mut self, nr: u32) -> std::io::Result<FixedFiles> {
+        FixedFiles::init(
+            self,
+            nr,
+            io_uring_rsrc_register {
+                nr,
+                flags: IORING_RSRC_REGISTER_SPARSE,
+                ..Default::default()
+            },
+        )
+    }
+}
@@ -0,0 +1,53 @@
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::rc::Rc;
+
+/// A handle on the kernel side of a ring, held by registered resources
+/// (buffer rings, fixed files, fixed buffers) so they can unregister
+/// themselves when dropped.
+///
+/// The handle owns a duplicate of the ring's file descriptor. The kernel
+/// keeps a ring alive until all descriptors referring to it are closed, so
+/// a resource can always be unregistered before its memory is freed, even
+/// if the `IoUring` it was registered with has already been dropped.
+#[derive(Clone)]
+pub(crate) struct RingHandle {
+    fd: Rc<OwnedFd>,
+}
+
+impl RingHandle {
+    pub(crate) fn new(ring_fd: RawFd) -> std::io::Result<RingHandle> {
+        let fd = unsafe { libc::fcntl(ring_fd, libc::F_DUPFD_CLOEXEC, 0) };
+        if fd < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(RingHandle {
+                fd: Rc::new(unsafe { OwnedFd::from_raw_fd(fd) }),
+            })
+        }
+    }
+
+    /// Issue an `io_uring_register` call for this ring.
+    ///
+    /// # Safety
+    /// `arg` must point to whatever `opcode` expects, and any memory it
+    /// registers must remain valid until it is unregistered.
+    pub(crate) unsafe fn register(
+        &self,
+        opcode: u32,
+        arg: *const libc::c_void,
+        nr_args: u32,
+    ) -> std::io::Result<i32> {
+        let ret = libc::syscall(
+            libc::SYS_io_uring_register,
+            self.fd.as_raw_fd(),
+            opcode,
+            arg,
+            nr_args,
+        );
+        if ret < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(ret as i32)
+        }
+    }
+}
@@ -6,33 +6,194 @@
 #![allow(non_snake_case)]
 
 use std::marker::PhantomPinned;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 use std::ptr::{null_mut, NonNull};
 use std::sync::atomic::{AtomicU32, Ordering};
 
 include! {concat!(env!("OUT_DIR"), "/iouring-sys.rs")}
 
+/// Define a set of flags wrapping their raw `u32` bits, with a constant for
+/// each flag and the usual set operations.
+macro_rules! bitset {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $($flag:ident = $bits:expr,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+        pub struct $name(u32);
+
+        impl $name {
+            $(pub const $flag: $name = $name($bits);)*
+
+            pub const fn empty() -> $name {
+                $name(0)
+            }
+
+            /// Create a set from raw bits.
+            pub const fn from_bits(bits: u32) -> $name {
+                $name(bits)
+            }
+
+            pub const fn bits(&self) -> u32 {
+                self.0
+            }
+
+            pub const fn is_empty(&self) -> bool {
+                self.0 == 0
+            }
+
+            /// Returns true if all flags in `other` are in this set.
+            pub const fn contains(&self, other: $name) -> bool {
+                self.0 & other.0 == other.0
+            }
+        }
+
+        impl std::ops::BitOr for $name {
+            type Output = $name;
+
+            fn bitor(self, rhs: $name) -> $name {
+                $name(self.0 | rhs.0)
+            }
+        }
+
+        impl std::ops::BitOrAssign for $name {
+            fn bitor_assign(&mut self, rhs: $name) {
+                self.0 |= rhs.0
+            }
+        }
+
+        impl std::ops::BitAnd for $name {
+            type Output = $name;
+
+            fn bitand(self, rhs: $name) -> $name {
+                $name(self.0 & rhs.0)
+            }
+        }
+    };
+}
+
 mod cqe;
 pub use cqe::*;
 mod sqe;
 pub use sqe::*;
 mod buf_ring;
 pub use buf_ring::*;
+mod builder;
+pub use builder::*;
+mod handle;
+pub(crate) use handle::*;
+mod socket;
+pub use socket::*;
+mod fixed_files;
+pub use fixed_files::*;
+mod fixed_bufs;
+pub use fixed_bufs::*;
+mod probe;
+pub use probe::*;
+mod registry;
+pub use registry::*;
+mod chain;
+pub use chain::*;
+mod submitter;
+pub use submitter::*;
+#[cfg(feature = "tokio")]
+mod async_ring;
+pub mod op;
+pub mod rt;
+#[cfg(feature = "tokio")]
+pub use async_ring::*;
 
 /// An IoUring structure, mostly so we can tell the
 /// Rust type system a bit more about our constraints.
 pub struct IoUring {
     pub(crate) ring: io_uring,
+    pub(crate) params: io_uring_params,
+    pub(crate) handle: RingHandle,
+    // One per SQ slot, see `SqeArgs`.
+    pub(crate) sqe_args: Box<[SqeArgs]>,
+    // See `IoUringBuilder::auto_flush_overflow`.
+    pub(crate) auto_flush: bool,
+    pub(crate) overflow_flushes: u64,
     _pin: PhantomPinned,
 }
 
+impl Drop for IoUring {
+    fn drop(&mut self) {
+        unsafe { io_uring_queue_exit(&mut self.ring) };
+    }
+}
+
+impl AsRawFd for IoUring {
+    /// Returns the ring's FD, e.g., to target it with `io_uring_prep_msg_ring`.
+    fn as_raw_fd(&self) -> RawFd {
+        self.ring.ring_fd
+    }
+}
+
+impl AsFd for IoUring {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // The FD stays open until the ring is dropped.
+        unsafe { BorrowedFd::borrow_raw(self.ring.ring_fd) }
+    }
+}
+
 impl IoUring {
-    pub fn init(depth: isize) -> IoUring {
-        let mut r = IoUring {
-            ring: Default::default(),
-            _pin: Default::default(),
-        };
-        unsafe { io_uring_queue_init(depth as u32, &mut r.ring, 0) };
-        r
+    /// Create a ring with `depth` SQ entries and no setup flags. Use
+    /// [`IoUring::builder`] for anything else.
+    pub fn init(depth: isize) -> std::io::Result<IoUring> {
+        Self::builder().entries(depth as u32).build()
+    }
+
+    /// Returns a builder used to configure setup flags for a new ring.
+    pub fn builder<'a>() -> IoUringBuilder<'a> {
+        IoUringBuilder::default()
+    }
+
+    /// Returns the parameters the ring was created with, as updated
+    /// by the kernel during setup.
+    pub fn params(&self) -> &io_uring_params {
+        &self.params
+    }
+
+    /// Returns the features supported by the kernel.
+    pub fn features(&self) -> Features {
+        Features::from_bits(self.params.features)
+    }
+
+    /// Have the kernel signal the eventfd `fd` whenever a CQE is posted, so
+    /// completions can be waited for with `epoll` and friends.
+    pub fn register_eventfd(&mut self, fd: RawFd) -> std::io::Result<()> {
+        let ret = unsafe { io_uring_register_eventfd(&mut self.ring, fd) };
+        if ret < 0 {
+            Err(std::io::Error::from_raw_os_error(-ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Like `register_eventfd`, but only signal for requests that completed
+    /// asynchronously, rather than inline during submission.
+    pub fn register_eventfd_async(&mut self, fd: RawFd) -> std::io::Result<()> {
+        let ret = unsafe { io_uring_register_eventfd_async(&mut self.ring, fd) };
+        if ret < 0 {
+            Err(std::io::Error::from_raw_os_error(-ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Stop signalling the eventfd registered with `register_eventfd` or
+    /// `register_eventfd_async`.
+    pub fn unregister_eventfd(&mut self) -> std::io::Result<()> {
+        let ret = unsafe { io_uring_unregister_eventfd(&mut self.ring) };
+        if ret < 0 {
+            Err(std::io::Error::from_raw_os_error(-ret))
+        } else {
+            Ok(())
+        }
     }
 
     /// Returns the underlying `io_uring` so one can directly
@@ -44,6 +205,9 @@ impl IoUring {
 
     /// Submit pending SQEs.
     ///
+    /// With SQPOLL this only enters the kernel if the SQ poll thread has
+    /// gone idle and needs waking up, see `io_uring_sq_needs_wakeup`.
+    ///
     /// Returns number of submitted tasks.
     pub fn submit(&mut self) -> i32 {
         unsafe { io_uring_submit(&mut self.ring) }
@@ -65,6 +229,26 @@ impl IoUring {
         }
     }
 
+    /// With SQPOLL, whether the SQ poll thread has gone idle, so that the
+    /// next `submit` needs a syscall to wake it up.
+    pub fn io_uring_sq_needs_wakeup(&mut self) -> bool {
+        let flag =
+            unsafe { AtomicU32::from_mut(&mut *self.ring.sq.kflags).load(Ordering::Relaxed) };
+        (flag & IORING_SQ_NEED_WAKEUP) != 0
+    }
+
+    /// With SQPOLL, wait until the SQ poll thread has consumed SQEs so that
+    /// there is room in the SQ. Returns immediately without SQPOLL or if
+    /// there already is room.
+    pub fn sq_wait(&mut self) -> std::io::Result<()> {
+        let ret = unsafe { io_uring_sqring_wait(&mut self.ring) };
+        if ret < 0 {
+            Err(std::io::Error::from_raw_os_error(-ret))
+        } else {
+            Ok(())
+        }
+    }
+
     /// Returns the number of SQEs avaialble.
     #[inline(always)]
     pub fn io_uring_sq_available(&mut self) -> u32 {
@@ -87,17 +271,62 @@ impl IoUring {
         (flag & IORING_SQ_CQ_OVERFLOW) != 0
     }
 
+    /// Move CQEs that overflowed into the CQ, as far as there is room.
+    /// Returns whether there were any to move.
+    pub fn flush_overflow(&mut self) -> std::io::Result<bool> {
+        if !self.io_uring_cq_has_overflow() {
+            return Ok(false);
+        }
+        let ret = unsafe { io_uring_get_events(&mut self.ring) };
+        if ret < 0 {
+            Err(std::io::Error::from_raw_os_error(-ret))
+        } else {
+            Ok(true)
+        }
+    }
+
+    /// Returns counts of CQ overflows so far.
+    pub fn cq_overflow(&mut self) -> CqOverflow {
+        let dropped =
+            unsafe { AtomicU32::from_mut(&mut *self.ring.cq.koverflow).load(Ordering::Relaxed) };
+        CqOverflow {
+            flushes: self.overflow_flushes,
+            dropped,
+        }
+    }
+
     /// Return a SQE from `ring` or `None` if no empty SQEs are
     /// available.
     pub fn io_uring_get_sqe(&mut self) -> std::option::Option<Sqe> {
-        let sq = &mut self.ring.sq;
-        let next = sq.sqe_tail + 1;
-        let shift = if (self.ring.flags & IORING_SETUP_SQE128) != 0 {
-            1
-        } else {
-            0
-        };
+        if self.io_uring_sq_space() == 0 {
+            return None;
+        }
+        let current = self.ring.sq.sqe_tail;
+        self.ring.sq.sqe_tail = current.wrapping_add(1);
+        Some(unsafe { self.sqe_at(current) })
+    }
+
+    /// Like `io_uring_get_sqe`, but if the SQ is full submit what is queued
+    /// to make room, with SQPOLL waiting for the kernel to consume it.
+    pub fn get_sqe_or_submit(&mut self) -> std::io::Result<Sqe<'_>> {
+        if self.io_uring_sq_space() == 0 {
+            let ret = self.submit();
+            if ret < 0 {
+                return Err(std::io::Error::from_raw_os_error(-ret));
+            }
+        }
+        if self.ring.flags & IORING_SETUP_SQPOLL != 0 && self.io_uring_sq_space() == 0 {
+            self.sq_wait()?;
+        }
+        self.io_uring_get_sqe()
+            .ok_or_else(|| std::io::Error::from_raw_os_error(libc::EBUSY))
+    }
 
+    /// Returns the number of SQEs that can still be handed out, which
+    /// unlike `io_uring_sq_available` accounts for what the kernel has
+    /// consumed since the last submission.
+    fn io_uring_sq_space(&mut self) -> u32 {
+        let sq = &mut self.ring.sq;
         // Sigh nightly only
         unsafe {
             let skhead = AtomicU32::from_mut(&mut *sq.khead);
@@ -106,15 +335,209 @@ impl IoUring {
             } else {
                 skhead.load(Ordering::Acquire)
             };
-            if next - head <= sq.ring_entries {
-                let current = sq.sqe_tail;
-                sq.sqe_tail = next;
-                Some(Sqe::init(
-                    sq.sqes.offset(((current & sq.ring_mask) << shift) as isize),
-                ))
-            } else {
-                None
+            sq.ring_entries - sq.sqe_tail.wrapping_sub(head)
+        }
+    }
+
+    /// Returns the SQE at position `tail` of the SQ.
+    ///
+    /// # Safety
+    /// The SQE must have been handed out by advancing `sqe_tail` past it,
+    /// and must not be in use elsewhere.
+    pub(crate) unsafe fn sqe_at(&mut self, tail: u32) -> Sqe<'_> {
+        let sq = &mut self.ring.sq;
+        let shift = if (self.ring.flags & IORING_SETUP_SQE128) != 0 {
+            1
+        } else {
+            0
+        };
+        Sqe::init(
+            sq.sqes.offset(((tail & sq.ring_mask) << shift) as isize),
+            self.sqe_args
+                .as_mut_ptr()
+                .add((tail & sq.ring_mask) as usize),
+            shift != 0,
+        )
+    }
+
+    /// Reserve `n` SQEs for a chain of linked requests, or return `None` if
+    /// the SQ does not have room for all of them. See [`SqeChain`].
+    pub fn chain(&mut self, n: u32) -> Option<SqeChain<'_>> {
+        if n > self.io_uring_sq_space() {
+            return None;
+        }
+        let start = self.ring.sq.sqe_tail;
+        self.ring.sq.sqe_tail = start.wrapping_add(n);
+        Some(SqeChain::new(self, start, n))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::mem::ManuallyDrop;
+    use std::ops::{Deref, DerefMut};
+
+    use super::*;
+
+    /// A ring in memory with no kernel side, for testing what only touches
+    /// the SQ and CQ. Its FD is invalid, so anything entering the kernel
+    /// fails with `EBADF`. It is leaked rather than torn down when dropped.
+    pub(crate) struct FakeRing {
+        ring: ManuallyDrop<IoUring>,
+        // SQ head, tail and flags, then CQ head, tail and overflow.
+        _words: Box<[u32; 6]>,
+        _sqes: Vec<io_uring_sqe>,
+        _cqes: Vec<io_uring_cqe>,
+    }
+
+    impl FakeRing {
+        /// A ring of `entries` SQEs and twice as many CQEs, set up with
+        /// `flags`.
+        pub(crate) fn new(entries: u32, flags: u32) -> FakeRing {
+            let mut words = Box::new([0; 6]);
+            let mut sqes: Vec<io_uring_sqe> = (0..entries)
+                .map(|_| unsafe { std::mem::zeroed() })
+                .collect();
+            let mut cqes: Vec<io_uring_cqe> =
+                (0..2 * entries).map(|_| Default::default()).collect();
+            let mut ring: io_uring = unsafe { std::mem::zeroed() };
+            ring.flags = flags;
+            ring.ring_fd = -1;
+            let [sq_head, sq_tail, sq_flags, cq_head, cq_tail, cq_overflow] = &mut *words;
+            ring.sq.khead = sq_head;
+            ring.sq.ktail = sq_tail;
+            ring.sq.kflags = sq_flags;
+            ring.sq.sqes = sqes.as_mut_ptr();
+            ring.sq.ring_mask = entries - 1;
+            ring.sq.ring_entries = entries;
+            ring.cq.khead = cq_head;
+            ring.cq.ktail = cq_tail;
+            ring.cq.koverflow = cq_overflow;
+            ring.cq.cqes = cqes.as_mut_ptr();
+            ring.cq.ring_mask = 2 * entries - 1;
+            ring.cq.ring_entries = 2 * entries;
+            let mut params: io_uring_params = unsafe { std::mem::zeroed() };
+            params.flags = flags;
+            params.sq_entries = entries;
+            params.cq_entries = 2 * entries;
+            let null = std::fs::File::open("/dev/null").unwrap();
+            let ring = IoUring {
+                ring,
+                params,
+                handle: RingHandle::new(null.as_raw_fd()).unwrap(),
+                sqe_args: (0..entries).map(|_| SqeArgs::default()).collect(),
+                auto_flush: false,
+                overflow_flushes: 0,
+                _pin: Default::default(),
+            };
+            FakeRing {
+                ring: ManuallyDrop::new(ring),
+                _words: words,
+                _sqes: sqes,
+                _cqes: cqes,
             }
         }
+
+        /// Have the kernel consume the SQEs up to `head`.
+        pub(crate) fn consume_sqes(&mut self, head: u32) {
+            unsafe { *self.ring.ring.sq.khead = head };
+        }
+
+        /// Returns the SQ tail the kernel sees, i.e., up to where SQEs
+        /// were submitted.
+        pub(crate) fn submitted(&self) -> u32 {
+            unsafe { *self.ring.ring.sq.ktail }
+        }
+
+        pub(crate) fn set_sq_flags(&mut self, flags: u32) {
+            unsafe { *self.ring.ring.sq.kflags = flags };
+        }
+
+        /// Post a CQE as the kernel would.
+        pub(crate) fn post_cqe(&mut self, user_data: u64, res: i32, flags: u32) {
+            let cq = &mut self.ring.ring.cq;
+            unsafe {
+                let tail = *cq.ktail;
+                *cq.cqes.add((tail & cq.ring_mask) as usize) = io_uring_cqe {
+                    user_data,
+                    res,
+                    flags,
+                    ..Default::default()
+                };
+                *cq.ktail = tail.wrapping_add(1);
+            }
+        }
+    }
+
+    impl Deref for FakeRing {
+        type Target = IoUring;
+
+        fn deref(&self) -> &IoUring {
+            &self.ring
+        }
+    }
+
+    impl DerefMut for FakeRing {
+        fn deref_mut(&mut self) -> &mut IoUring {
+            &mut self.ring
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeRing;
+
+    fn fill_sq(ring: &mut IoUring) {
+        while let Some(sqe) = ring.io_uring_get_sqe() {
+            sqe.io_uring_prep_nop().finalize();
+        }
+    }
+
+    #[test]
+    fn sq_needs_wakeup() {
+        let mut ring = FakeRing::new(4, IORING_SETUP_SQPOLL);
+        assert!(!ring.io_uring_sq_needs_wakeup());
+        ring.set_sq_flags(IORING_SQ_NEED_WAKEUP | IORING_SQ_CQ_OVERFLOW);
+        assert!(ring.io_uring_sq_needs_wakeup());
+        ring.set_sq_flags(IORING_SQ_CQ_OVERFLOW);
+        assert!(!ring.io_uring_sq_needs_wakeup());
+    }
+
+    #[test]
+    fn sq_wait() {
+        // Without SQPOLL there is nothing to wait for, even if the SQ is full.
+        let mut ring = FakeRing::new(4, 0);
+        fill_sq(&mut ring);
+        assert!(ring.sq_wait().is_ok());
+
+        let mut ring = FakeRing::new(4, IORING_SETUP_SQPOLL);
+        fill_sq(&mut ring);
+        ring.consume_sqes(1);
+        assert!(ring.sq_wait().is_ok());
+        // With the SQ full this enters the kernel, which fails for the fake
+        // ring.
+        fill_sq(&mut ring);
+        assert_eq!(
+            ring.sq_wait().unwrap_err().raw_os_error(),
+            Some(libc::EBADF)
+        );
+    }
+
+    #[test]
+    fn get_sqe_or_submit_sq_poll() {
+        let mut ring = FakeRing::new(4, IORING_SETUP_SQPOLL);
+        fill_sq(&mut ring);
+        // Submitting does not make room until the SQ poll thread consumes
+        // the SQEs, so this waits for it.
+        let err = ring.get_sqe_or_submit().err().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+        assert_eq!(ring.submitted(), 4);
+
+        ring.consume_sqes(2);
+        assert!(ring.get_sqe_or_submit().is_ok());
+        assert!(ring.get_sqe_or_submit().is_ok());
+        assert!(ring.io_uring_get_sqe().is_none());
     }
 }
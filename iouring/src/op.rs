@@ -0,0 +1,724 @@
+//! Operations that own their buffers while in flight.
+//!
+//! The `prep_*` methods on [`Sqe`] are mostly `unsafe` because the caller has
+//! to keep buffers alive until the kernel is done with them. The types here
+//! take ownership of the buffer instead, and [`Ops`] keeps them in a slab
+//! keyed by `user_data` until their CQE is reaped, after which the buffer is
+//! handed back along with the result.
+//!
+//! ```ignore
+//! let mut ops = Ops::new();
+//! let id = ops.push(&mut ring, op::Read::new(fd, vec![0u8; 4096], 0)).unwrap();
+//! ring.submit();
+//! // For each CQE reaped from `ring`:
+//! unsafe { ops.complete(cqe) };
+//! // Once completed:
+//! let (res, buf) = ops.take(id).ok().unwrap();
+//! ```
+
+use std::any::Any;
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+
+use super::*;
+
+/// A buffer the kernel can read from.
+///
+/// # Safety
+/// The memory behind `stable_ptr` must stay valid, and at the same address,
+/// when the buffer is moved, and for as long as it is not dropped or mutated.
+pub unsafe trait IoBuf: 'static {
+    fn stable_ptr(&self) -> *const u8;
+
+    /// Returns the number of initialized bytes, which is what gets written.
+    fn bytes_init(&self) -> usize;
+}
+
+/// A buffer the kernel can write into, starting at its first byte.
+///
+/// # Safety
+/// As for [`IoBuf`], and `stable_mut_ptr` must be valid for writes of
+/// `bytes_total` bytes.
+pub unsafe trait IoBufMut: IoBuf {
+    fn stable_mut_ptr(&mut self) -> *mut u8;
+
+    /// Returns the number of bytes the kernel may write.
+    fn bytes_total(&mut self) -> usize;
+
+    /// Record that the kernel initialized the first `n` bytes.
+    ///
+    /// # Safety
+    /// The first `n` bytes must have been initialized.
+    unsafe fn set_init(&mut self, n: usize);
+}
+
+unsafe impl IoBuf for Vec<u8> {
+    fn stable_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+}
+
+unsafe impl IoBufMut for Vec<u8> {
+    fn stable_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut_ptr()
+    }
+
+    fn bytes_total(&mut self) -> usize {
+        self.capacity()
+    }
+
+    unsafe fn set_init(&mut self, n: usize) {
+        unsafe { self.set_len(n) };
+    }
+}
+
+unsafe impl IoBuf for Box<[u8]> {
+    fn stable_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+}
+
+unsafe impl IoBufMut for Box<[u8]> {
+    fn stable_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut_ptr()
+    }
+
+    fn bytes_total(&mut self) -> usize {
+        self.len()
+    }
+
+    unsafe fn set_init(&mut self, _n: usize) {}
+}
+
+unsafe impl IoBuf for &'static [u8] {
+    fn stable_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+}
+
+/// An operation that owns everything the kernel accesses while it is in
+/// flight.
+///
+/// # Safety
+/// `prep` may only point the kernel at memory owned by `self`, and that
+/// memory must stay valid until `complete` is called. [`Ops`] does not move
+/// an op between `prep` and `complete`, so this includes fields of `self`.
+pub unsafe trait Op: 'static {
+    type Output: 'static;
+
+    /// The opcode used, e.g., to check it with [`Probe::supports`].
+    const OPCODE: Opcode;
+
+    /// Fill in `sqe` for this operation. `user_data` is set by the caller.
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a>;
+
+    /// Build the output from the CQE of this operation.
+    fn complete(self, cqe: &io_uring_cqe) -> Self::Output;
+
+    /// Handle a CQE flagged `IORING_CQE_F_MORE`, which is followed by more
+    /// CQEs for this operation. `complete` is called with the final one.
+    fn more(&mut self, _cqe: &io_uring_cqe) {}
+}
+
+/// Read from `fd` at `offset` into the buffer, up to its `bytes_total`.
+/// Completes with the number of bytes read and the buffer.
+pub struct Read<B> {
+    fd: RawFd,
+    buf: B,
+    offset: u64,
+}
+
+impl<B: IoBufMut> Read<B> {
+    /// Use an `offset` of `u64::MAX` to read from the current file position.
+    pub fn new(fd: RawFd, buf: B, offset: u64) -> Self {
+        Read { fd, buf, offset }
+    }
+}
+
+unsafe impl<B: IoBufMut> Op for Read<B> {
+    type Output = (std::io::Result<usize>, B);
+    const OPCODE: Opcode = Opcode::Read;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        let len = self.buf.bytes_total();
+        unsafe { sqe.io_uring_prep_read(self.fd, self.buf.stable_mut_ptr(), len, self.offset) }
+    }
+
+    fn complete(mut self, cqe: &io_uring_cqe) -> Self::Output {
+        let res = cqe.result().map(|n| n as usize);
+        if let Ok(n) = res {
+            unsafe { self.buf.set_init(n) };
+        }
+        (res, self.buf)
+    }
+}
+
+/// Write the initialized bytes of the buffer to `fd` at `offset`.
+/// Completes with the number of bytes written and the buffer.
+pub struct Write<B> {
+    fd: RawFd,
+    buf: B,
+    offset: u64,
+}
+
+impl<B: IoBuf> Write<B> {
+    /// Use an `offset` of `u64::MAX` to write at the current file position.
+    pub fn new(fd: RawFd, buf: B, offset: u64) -> Self {
+        Write { fd, buf, offset }
+    }
+}
+
+unsafe impl<B: IoBuf> Op for Write<B> {
+    type Output = (std::io::Result<usize>, B);
+    const OPCODE: Opcode = Opcode::Write;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        unsafe {
+            sqe.io_uring_prep_write(
+                self.fd,
+                self.buf.stable_ptr(),
+                self.buf.bytes_init(),
+                self.offset,
+            )
+        }
+    }
+
+    fn complete(self, cqe: &io_uring_cqe) -> Self::Output {
+        (cqe.result().map(|n| n as usize), self.buf)
+    }
+}
+
+/// Read from `fd` at `offset` into the fixed buffer, up to its capacity.
+/// Completes with the number of bytes read and the buffer, with its length
+/// set to that number.
+pub struct ReadFixed {
+    fd: RawFd,
+    buf: FixedBuf,
+    offset: u64,
+}
+
+impl ReadFixed {
+    /// Use an `offset` of `u64::MAX` to read from the current file position.
+    pub fn new(fd: RawFd, buf: FixedBuf, offset: u64) -> Self {
+        ReadFixed { fd, buf, offset }
+    }
+}
+
+unsafe impl Op for ReadFixed {
+    type Output = (std::io::Result<usize>, FixedBuf);
+    const OPCODE: Opcode = Opcode::ReadFixed;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        unsafe { sqe.io_uring_prep_read_fixed(self.fd, &mut self.buf, self.offset) }
+    }
+
+    fn complete(mut self, cqe: &io_uring_cqe) -> Self::Output {
+        let res = cqe.result().map(|n| n as usize);
+        if let Ok(n) = res {
+            self.buf.set_len(n);
+        }
+        (res, self.buf)
+    }
+}
+
+/// Write the contents of the fixed buffer to `fd` at `offset`. Completes
+/// with the number of bytes written and the buffer.
+pub struct WriteFixed {
+    fd: RawFd,
+    buf: FixedBuf,
+    offset: u64,
+}
+
+impl WriteFixed {
+    /// Use an `offset` of `u64::MAX` to write at the current file position.
+    pub fn new(fd: RawFd, buf: FixedBuf, offset: u64) -> Self {
+        WriteFixed { fd, buf, offset }
+    }
+}
+
+unsafe impl Op for WriteFixed {
+    type Output = (std::io::Result<usize>, FixedBuf);
+    const OPCODE: Opcode = Opcode::WriteFixed;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        unsafe { sqe.io_uring_prep_write_fixed(self.fd, &self.buf, self.offset) }
+    }
+
+    fn complete(self, cqe: &io_uring_cqe) -> Self::Output {
+        (cqe.result().map(|n| n as usize), self.buf)
+    }
+}
+
+/// Receive from the socket `fd` into the buffer, up to its `bytes_total`.
+/// Completes with the number of bytes received and the buffer.
+pub struct Recv<B> {
+    fd: RawFd,
+    buf: B,
+    flags: u32,
+}
+
+impl<B: IoBufMut> Recv<B> {
+    /// `flags` are the `MSG_*` flags of `recv(2)`.
+    pub fn new(fd: RawFd, buf: B, flags: u32) -> Self {
+        Recv { fd, buf, flags }
+    }
+}
+
+unsafe impl<B: IoBufMut> Op for Recv<B> {
+    type Output = (std::io::Result<usize>, B);
+    const OPCODE: Opcode = Opcode::Recv;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        let len = self.buf.bytes_total();
+        unsafe { sqe.io_uring_prep_recv(self.fd, self.buf.stable_mut_ptr(), len, self.flags) }
+    }
+
+    fn complete(mut self, cqe: &io_uring_cqe) -> Self::Output {
+        let res = cqe.result().map(|n| n as usize);
+        if let Ok(n) = res {
+            unsafe { self.buf.set_init(n) };
+        }
+        (res, self.buf)
+    }
+}
+
+/// Send the initialized bytes of the buffer on the socket `fd`.
+/// Completes with the number of bytes sent and the buffer.
+pub struct Send<B> {
+    fd: RawFd,
+    buf: B,
+    flags: u32,
+}
+
+impl<B: IoBuf> Send<B> {
+    /// `flags` are the `MSG_*` flags of `send(2)`.
+    pub fn new(fd: RawFd, buf: B, flags: u32) -> Self {
+        Send { fd, buf, flags }
+    }
+}
+
+unsafe impl<B: IoBuf> Op for Send<B> {
+    type Output = (std::io::Result<usize>, B);
+    const OPCODE: Opcode = Opcode::Send;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        unsafe {
+            sqe.io_uring_prep_send(
+                self.fd,
+                self.buf.stable_ptr(),
+                self.buf.bytes_init(),
+                self.flags,
+            )
+        }
+    }
+
+    fn complete(self, cqe: &io_uring_cqe) -> Self::Output {
+        (cqe.result().map(|n| n as usize), self.buf)
+    }
+}
+
+/// Zero-copy send of the contents of the fixed buffer on the socket `fd`.
+/// Completes with the number of bytes sent and the buffer, once the kernel
+/// is done with the buffer.
+pub struct SendZcFixed {
+    fd: RawFd,
+    buf: FixedBuf,
+    flags: u32,
+    // The result of the send, kept until the notification.
+    res: Option<std::io::Result<usize>>,
+}
+
+impl SendZcFixed {
+    /// `flags` are the `MSG_*` flags of `send(2)`.
+    pub fn new(fd: RawFd, buf: FixedBuf, flags: u32) -> Self {
+        SendZcFixed {
+            fd,
+            buf,
+            flags,
+            res: None,
+        }
+    }
+}
+
+unsafe impl Op for SendZcFixed {
+    type Output = (std::io::Result<usize>, FixedBuf);
+    const OPCODE: Opcode = Opcode::SendZc;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        unsafe { sqe.io_uring_prep_send_zc_fixed(self.fd, &self.buf, self.flags) }
+    }
+
+    fn more(&mut self, cqe: &io_uring_cqe) {
+        self.res = Some(cqe.result().map(|n| n as usize));
+    }
+
+    fn complete(self, cqe: &io_uring_cqe) -> Self::Output {
+        // The send posts no notification if it failed.
+        let res = match self.res {
+            Some(res) if cqe.is_notification() => res,
+            _ => cqe.result().map(|n| n as usize),
+        };
+        (res, self.buf)
+    }
+}
+
+/// `statx` of `path` relative to `dfd`, fetching the `libc::STATX_*` fields
+/// in `mask`. Completes with the result.
+pub struct Statx {
+    dfd: RawFd,
+    path: CString,
+    flags: i32,
+    mask: u32,
+    buf: crate::Statx,
+}
+
+impl Statx {
+    /// `flags` are the `AT_*` flags of `statx(2)`.
+    pub fn new(dfd: RawFd, path: CString, flags: i32, mask: u32) -> Self {
+        Statx {
+            dfd,
+            path,
+            flags,
+            mask,
+            buf: crate::Statx::new(),
+        }
+    }
+}
+
+unsafe impl Op for Statx {
+    type Output = std::io::Result<crate::Statx>;
+    const OPCODE: Opcode = Opcode::Statx;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        // The path is kept here, as older kernels read it late.
+        unsafe {
+            sqe.io_uring_prep_statx(
+                self.dfd,
+                self.path.as_ptr(),
+                self.flags,
+                self.mask,
+                &mut *self.buf.0,
+            )
+        }
+    }
+
+    fn complete(self, cqe: &io_uring_cqe) -> Self::Output {
+        cqe.result().map(|_| self.buf)
+    }
+}
+
+/// Accept a connection on the listening socket `fd`. Completes with the new
+/// socket and, for IP sockets, the peer address.
+pub struct Accept {
+    fd: RawFd,
+    addr: SockAddrBuf,
+    flags: u32,
+}
+
+impl Accept {
+    /// `flags` are the `SOCK_*` flags of `accept4(2)`.
+    pub fn new(fd: RawFd, flags: u32) -> Self {
+        Accept {
+            fd,
+            addr: SockAddrBuf::default(),
+            flags,
+        }
+    }
+}
+
+unsafe impl Op for Accept {
+    type Output = std::io::Result<(OwnedFd, Option<SocketAddr>)>;
+    const OPCODE: Opcode = Opcode::Accept;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        sqe.io_uring_prep_accept(
+            self.fd,
+            self.addr.as_mut_ptr(),
+            &mut self.addr.len,
+            self.flags,
+        )
+    }
+
+    fn complete(self, cqe: &io_uring_cqe) -> Self::Output {
+        let fd = unsafe { OwnedFd::from_raw_fd(cqe.result()? as RawFd) };
+        Ok((fd, self.addr.to_socket_addr()))
+    }
+}
+
+/// Accept a connection on the listening socket `fd` into a free slot of the
+/// fixed file table. Completes with the slot, to be taken over with
+/// [`FixedFiles::adopt`].
+pub struct AcceptDirect {
+    fd: RawFd,
+    flags: u32,
+}
+
+impl AcceptDirect {
+    /// `flags` are the `SOCK_*` flags of `accept4(2)`.
+    pub fn new(fd: RawFd, flags: u32) -> Self {
+        AcceptDirect { fd, flags }
+    }
+}
+
+unsafe impl Op for AcceptDirect {
+    type Output = std::io::Result<u32>;
+    const OPCODE: Opcode = Opcode::Accept;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        sqe.io_uring_prep_accept_direct(
+            self.fd,
+            null_mut(),
+            null_mut(),
+            self.flags,
+            IORING_FILE_INDEX_ALLOC,
+        )
+    }
+
+    fn complete(self, cqe: &io_uring_cqe) -> Self::Output {
+        cqe.result()
+    }
+}
+
+/// Post a CQE to the ring `ring_fd`, with `len` as its result and `data` as
+/// its user data.
+pub struct MsgRing {
+    ring_fd: RawFd,
+    len: u32,
+    data: u64,
+}
+
+impl MsgRing {
+    pub fn new(ring_fd: RawFd, len: u32, data: u64) -> Self {
+        MsgRing { ring_fd, len, data }
+    }
+}
+
+unsafe impl Op for MsgRing {
+    type Output = std::io::Result<()>;
+    const OPCODE: Opcode = Opcode::MsgRing;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        sqe.io_uring_prep_msg_ring(self.ring_fd, self.len, self.data, 0)
+    }
+
+    fn complete(self, cqe: &io_uring_cqe) -> Self::Output {
+        cqe.result().map(drop)
+    }
+}
+
+/// Move a fixed file to the fixed file table of the ring `ring_fd`, in a
+/// slot picked by the kernel. That ring gets a CQE with `data` as its user
+/// data and the slot as its result. Our slot is cleared on completion.
+pub struct SendFixedFd {
+    ring_fd: RawFd,
+    fd: FixedFd,
+    data: u64,
+}
+
+impl SendFixedFd {
+    pub fn new(ring_fd: RawFd, fd: FixedFd, data: u64) -> Self {
+        SendFixedFd { ring_fd, fd, data }
+    }
+}
+
+unsafe impl Op for SendFixedFd {
+    type Output = std::io::Result<()>;
+    const OPCODE: Opcode = Opcode::MsgRing;
+
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        sqe.io_uring_prep_msg_ring_fd(
+            self.ring_fd,
+            self.fd.slot(),
+            IORING_FILE_INDEX_ALLOC,
+            self.data,
+            0,
+        )
+    }
+
+    fn complete(self, cqe: &io_uring_cqe) -> Self::Output {
+        cqe.result().map(drop)
+    }
+}
+
+// `Op` with the type erased, so ops of different types can share the slab.
+trait ErasedOp {
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a>;
+    fn complete(self: Box<Self>, cqe: &io_uring_cqe) -> Box<dyn Any>;
+    fn more(&mut self, cqe: &io_uring_cqe);
+}
+
+impl<O: Op> ErasedOp for O {
+    fn prep<'a>(&mut self, sqe: Sqe<'a>) -> Sqe<'a> {
+        Op::prep(self, sqe)
+    }
+
+    fn complete(self: Box<Self>, cqe: &io_uring_cqe) -> Box<dyn Any> {
+        Box::new(Op::complete(*self, cqe))
+    }
+
+    fn more(&mut self, cqe: &io_uring_cqe) {
+        Op::more(self, cqe)
+    }
+}
+
+enum Slot {
+    InFlight(Box<dyn ErasedOp>),
+    // In flight, but its output is dropped on completion.
+    Detached(Box<dyn ErasedOp>),
+    Done(Box<dyn Any>),
+}
+
+/// Set in the `user_data` of every op pushed to an [`Ops`], so its CQEs can
+/// be told apart from those of requests submitted directly.
+pub const OPS_USER_DATA_TAG: u64 = 1 << 63;
+
+/// Identifies an op pushed to an [`Ops`], used to take its output.
+pub struct OpId<O> {
+    token: Token,
+    _marker: PhantomData<fn() -> O>,
+}
+
+impl<O> OpId<O> {
+    /// Returns the `user_data` the op was submitted with.
+    pub fn user_data(&self) -> u64 {
+        self.token.user_data() | OPS_USER_DATA_TAG
+    }
+}
+
+/// The slab of in-flight ops. Ops still in flight when this is dropped are
+/// leaked, as the kernel may still be using their buffers.
+#[derive(Default)]
+pub struct Ops {
+    ops: Registry<Slot>,
+}
+
+impl Drop for Ops {
+    fn drop(&mut self) {
+        for slot in self.ops.drain() {
+            if let Slot::InFlight(op) | Slot::Detached(op) = slot {
+                std::mem::forget(op);
+            }
+        }
+    }
+}
+
+impl Ops {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of ops that have not completed.
+    pub fn in_flight(&self) -> usize {
+        self.ops
+            .iter()
+            .filter(|(_, slot)| matches!(slot, Slot::InFlight(_) | Slot::Detached(_)))
+            .count()
+    }
+
+    /// Queue `op` on `ring`. Returns `op` back if no SQE is available.
+    pub fn push<O: Op>(&mut self, ring: &mut IoUring, op: O) -> Result<OpId<O>, O> {
+        let Some(sqe) = ring.io_uring_get_sqe() else {
+            return Err(op);
+        };
+        // Box before `prep`, so the op does not move while in flight.
+        let mut op: Box<dyn ErasedOp> = Box::new(op);
+        let sqe = op.prep(sqe);
+        let token = self.ops.insert(Slot::InFlight(op));
+        sqe.set_sqe_data(token.user_data() | OPS_USER_DATA_TAG)
+            .finalize();
+        Ok(OpId {
+            token,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Complete the op `cqe` belongs to, unless more CQEs follow for it.
+    /// Returns false if `cqe` is not for one of our ops, or is for one that
+    /// already completed.
+    ///
+    /// # Safety
+    /// `cqe` must have been reaped from the ring the ops were pushed to, as
+    /// completing an op hands its buffers back to the caller.
+    pub unsafe fn complete(&mut self, cqe: &io_uring_cqe) -> bool {
+        if cqe.user_data & OPS_USER_DATA_TAG == 0 {
+            return false;
+        }
+        let token = Token::from_user_data(cqe.user_data & !OPS_USER_DATA_TAG);
+        let Some(slot) = self.ops.get_mut(token) else {
+            return false;
+        };
+        if cqe.expect_more_notifications() {
+            if let Slot::InFlight(op) | Slot::Detached(op) = slot {
+                op.more(cqe);
+                return true;
+            }
+            return false;
+        }
+        // The placeholder does not allocate, and is replaced right away.
+        match std::mem::replace(slot, Slot::Done(Box::new(()))) {
+            Slot::InFlight(op) => *slot = Slot::Done(op.complete(cqe)),
+            Slot::Detached(op) => {
+                drop(op.complete(cqe));
+                self.ops.remove(token);
+            }
+            done => {
+                *slot = done;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Give up on an op. Its output is dropped once it completes, or right
+    /// away if it already has. Returns true if it is still in flight, e.g.,
+    /// so the caller can cancel it.
+    pub fn detach<O: Op>(&mut self, id: OpId<O>) -> bool {
+        let Some(slot) = self.ops.get_mut(id.token) else {
+            return false;
+        };
+        if let Slot::InFlight(_) = slot {
+            if let Slot::InFlight(op) = std::mem::replace(slot, Slot::Done(Box::new(()))) {
+                *slot = Slot::Detached(op);
+            }
+            return true;
+        }
+        self.ops.remove(id.token);
+        false
+    }
+
+    /// Returns true if the op has completed, and `take` will succeed. Never
+    /// true for an `id` from another `Ops`, unless it happens to match an op
+    /// of the same type.
+    pub fn is_done<O: Op>(&self, id: &OpId<O>) -> bool {
+        match self.ops.get(id.token) {
+            Some(Slot::Done(output)) => output.is::<O::Output>(),
+            _ => false,
+        }
+    }
+
+    /// Take the output of a completed op, or get `id` back if it has not
+    /// completed yet, or is not for one of our ops.
+    pub fn take<O: Op>(&mut self, id: OpId<O>) -> Result<O::Output, OpId<O>> {
+        if !self.is_done(&id) {
+            return Err(id);
+        }
+        match self.ops.remove(id.token) {
+            Some(Slot::Done(output)) => output.downcast().map(|output| *output).map_err(|_| id),
+            _ => Err(id),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::ptr::NonNull;
+
+use super::*;
+
+/// An io_uring operation, as reported by [`Probe::supports`].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Nop = IORING_OP_NOP as u8,
+    Readv = IORING_OP_READV as u8,
+    Writev = IORING_OP_WRITEV as u8,
+    Fsync = IORING_OP_FSYNC as u8,
+    ReadFixed = IORING_OP_READ_FIXED as u8,
+    WriteFixed = IORING_OP_WRITE_FIXED as u8,
+    PollAdd = IORING_OP_POLL_ADD as u8,
+    PollRemove = IORING_OP_POLL_REMOVE as u8,
+    SyncFileRange = IORING_OP_SYNC_FILE_RANGE as u8,
+    Sendmsg = IORING_OP_SENDMSG as u8,
+    Recvmsg = IORING_OP_RECVMSG as u8,
+    Timeout = IORING_OP_TIMEOUT as u8,
+    TimeoutRemove = IORING_OP_TIMEOUT_REMOVE as u8,
+    Accept = IORING_OP_ACCEPT as u8,
+    AsyncCancel = IORING_OP_ASYNC_CANCEL as u8,
+    LinkTimeout = IORING_OP_LINK_TIMEOUT as u8,
+    Connect = IORING_OP_CONNECT as u8,
+    Fallocate = IORING_OP_FALLOCATE as u8,
+    Openat = IORING_OP_OPENAT as u8,
+    Close = IORING_OP_CLOSE as u8,
+    FilesUpdate = IORING_OP_FILES_UPDATE as u8,
+    Statx = IORING_OP_STATX as u8,
+    Read = IORING_OP_READ as u8,
+    Write = IORING_OP_WRITE as u8,
+    Fadvise = IORING_OP_FADVISE as u8,
+    Madvise = IORING_OP_MADVISE as u8,
+    Send = IORING_OP_SEND as u8,
+    Recv = IORING_OP_RECV as u8,
+    Openat2 = IORING_OP_OPENAT2 as u8,
+    EpollCtl = IORING_OP_EPOLL_CTL as u8,
+    Splice = IORING_OP_SPLICE as u8,
+    ProvideBuffers = IORING_OP_PROVIDE_BUFFERS as u8,
+    RemoveBuffers = IORING_OP_REMOVE_BUFFERS as u8,
+    Tee = IORING_OP_TEE as u8,
+    Shutdown = IORING_OP_SHUTDOWN as u8,
+    Renameat = IORING_OP_RENAMEAT as u8,
+    Unlinkat = IORING_OP_UNLINKAT as u8,
+    Mkdirat = IORING_OP_MKDIRAT as u8,
+    Symlinkat = IORING_OP_SYMLINKAT as u8,
+    Linkat = IORING_OP_LINKAT as u8,
+    MsgRing = IORING_OP_MSG_RING as u8,
+    Fsetxattr = IORING_OP_FSETXATTR as u8,
+    Setxattr = IORING_OP_SETXATTR as u8,
+    Fgetxattr = IORING_OP_FGETXATTR as u8,
+    Getxattr = IORING_OP_GETXATTR as u8,
+    Socket = IORING_OP_SOCKET as u8,
+    UringCmd = IORING_OP_URING_CMD as u8,
+    SendZc = IORING_OP_SEND_ZC as u8,
+    SendmsgZc = IORING_OP_SENDMSG_ZC as u8,
+    // Not in older kernel headers, require 6.11.
+    Bind = 56,
+    Listen = 57,
+}
+
+/// The operations supported by the running kernel, see [`IoUring::probe`].
+pub struct Probe {
+    probe: NonNull<io_uring_probe>,
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        unsafe { io_uring_free_probe(self.probe.as_ptr()) };
+    }
+}
+
+impl Probe {
+    /// Returns true if the kernel supports `op`. This says nothing about
+    /// flags added to an operation later, e.g., multishot accept.
+    pub fn supports(&self, op: Opcode) -> bool {
+        let probe = unsafe { self.probe.as_ref() };
+        if op as u8 > probe.last_op || op as u8 >= probe.ops_len {
+            return false;
+        }
+        let ops = unsafe { probe.ops.as_slice(probe.ops_len as usize) };
+        ops[op as usize].flags as u32 & IO_URING_OP_SUPPORTED != 0
+    }
+}
+
+impl IoUring {
+    /// Ask the kernel which operations it supports. Fails on kernels too old
+    /// to be probed (before 5.6).
+    pub fn probe(&mut self) -> std::io::Result<Probe> {
+        let probe = unsafe { io_uring_get_probe_ring(&mut self.ring) };
+        NonNull::new(probe)
+            .map(|probe| Probe { probe })
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "kernel does not support io_uring probing",
+                )
+            })
+    }
+}
+
+bitset! {
+    /// The `IORING_FEAT_*` bits reported by the kernel at setup, see
+    /// [`IoUring::features`].
+    pub struct Features {
+        SINGLE_MMAP = IORING_FEAT_SINGLE_MMAP,
+        NODROP = IORING_FEAT_NODROP,
+        SUBMIT_STABLE = IORING_FEAT_SUBMIT_STABLE,
+        RW_CUR_POS = IORING_FEAT_RW_CUR_POS,
+        CUR_PERSONALITY = IORING_FEAT_CUR_PERSONALITY,
+        FAST_POLL = IORING_FEAT_FAST_POLL,
+        POLL_32BITS = IORING_FEAT_POLL_32BITS,
+        SQPOLL_NONFIXED = IORING_FEAT_SQPOLL_NONFIXED,
+        EXT_ARG = IORING_FEAT_EXT_ARG,
+        NATIVE_WORKERS = IORING_FEAT_NATIVE_WORKERS,
+        RSRC_TAGS = IORING_FEAT_RSRC_TAGS,
+        CQE_SKIP = IORING_FEAT_CQE_SKIP,
+        LINKED_FILE = IORING_FEAT_LINKED_FILE,
+    }
+}
@@ -0,0 +1,178 @@
+use super::*;
+
+/// Identifies an entry in a [`Registry`], and is what requests are submitted
+/// with as their `user_data`.
+///
+/// The low 32 bits hold the slot and the next 31 bits its generation, so a
+/// token for a removed entry is not mistaken for whatever reuses the slot.
+/// The top bit is never set, leaving it free for tagging other requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Token(u64);
+
+const GENERATION_MASK: u32 = u32::MAX >> 1;
+
+impl Token {
+    fn new(index: u32, generation: u32) -> Token {
+        Token((generation as u64) << 32 | index as u64)
+    }
+
+    fn index(self) -> usize {
+        self.0 as u32 as usize
+    }
+
+    fn generation(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    /// Returns the value to pass to `Sqe::set_sqe_data`.
+    pub fn user_data(self) -> u64 {
+        self.0
+    }
+
+    /// Recover the token from a CQE's `user_data`.
+    pub fn from_user_data(user_data: u64) -> Token {
+        Token(user_data)
+    }
+}
+
+struct Entry<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// A CQE routed to its entry by [`Registry::dispatch`].
+pub enum Completion<'a, T> {
+    /// More CQEs will follow for this request (`IORING_CQE_F_MORE`), so the
+    /// entry stays registered.
+    More(&'a mut T),
+    /// The final CQE for this request, the entry has been removed.
+    Last(T),
+}
+
+impl<T> Completion<'_, T> {
+    pub fn value(&self) -> &T {
+        match self {
+            Completion::More(value) => value,
+            Completion::Last(value) => value,
+        }
+    }
+
+    pub fn is_last(&self) -> bool {
+        matches!(self, Completion::Last(_))
+    }
+}
+
+/// Per-request state for requests in flight, keyed by the `user_data` they
+/// were submitted with. `T` is whatever is needed to handle the CQEs, e.g.,
+/// an enum of request kinds or a boxed callback.
+pub struct Registry<T> {
+    entries: Vec<Entry<T>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Self {
+        Registry {
+            entries: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> Registry<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of registered entries.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Register `value`, returning the token to submit the request with.
+    pub fn insert(&mut self, value: T) -> Token {
+        self.len += 1;
+        if let Some(index) = self.free.pop() {
+            let entry = &mut self.entries[index as usize];
+            entry.value = Some(value);
+            return Token::new(index, entry.generation);
+        }
+        self.entries.push(Entry {
+            generation: 0,
+            value: Some(value),
+        });
+        Token::new(self.entries.len() as u32 - 1, 0)
+    }
+
+    /// Returns the entry for `token`, or `None` if it is stale.
+    pub fn get(&self, token: Token) -> Option<&T> {
+        self.entries
+            .get(token.index())
+            .filter(|entry| entry.generation == token.generation())?
+            .value
+            .as_ref()
+    }
+
+    /// Returns the entry for `token`, or `None` if it is stale.
+    pub fn get_mut(&mut self, token: Token) -> Option<&mut T> {
+        self.entries
+            .get_mut(token.index())
+            .filter(|entry| entry.generation == token.generation())?
+            .value
+            .as_mut()
+    }
+
+    /// Remove the entry for `token`, or return `None` if it is stale. Later
+    /// CQEs carrying `token` are then ignored by `dispatch`.
+    pub fn remove(&mut self, token: Token) -> Option<T> {
+        let entry = self
+            .entries
+            .get_mut(token.index())
+            .filter(|entry| entry.generation == token.generation())?;
+        let value = entry.value.take()?;
+        entry.generation = entry.generation.wrapping_add(1) & GENERATION_MASK;
+        self.free.push(token.index() as u32);
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Returns the registered entries and their tokens.
+    pub fn iter(&self) -> impl Iterator<Item = (Token, &T)> {
+        self.entries.iter().enumerate().filter_map(|(i, entry)| {
+            let value = entry.value.as_ref()?;
+            Some((Token::new(i as u32, entry.generation), value))
+        })
+    }
+
+    /// Remove all entries, invalidating their tokens.
+    pub fn drain(&mut self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len);
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            if let Some(value) = entry.value.take() {
+                entry.generation = entry.generation.wrapping_add(1) & GENERATION_MASK;
+                self.free.push(i as u32);
+                values.push(value);
+            }
+        }
+        self.len = 0;
+        values
+    }
+
+    /// Route `cqe` to its entry, removing the entry unless more CQEs will
+    /// follow. Returns `None` if the token in `user_data` is stale, or was
+    /// not issued by this registry.
+    pub fn dispatch(&mut self, cqe: &io_uring_cqe) -> Option<Completion<'_, T>> {
+        let token = Token::from_user_data(cqe.user_data);
+        if cqe.flags & IORING_CQE_F_MORE != 0 {
+            self.get_mut(token).map(Completion::More)
+        } else {
+            self.remove(token).map(Completion::Last)
+        }
+    }
+}
@@ -0,0 +1,452 @@
+//! A single-threaded executor driven by an [`IoUring`].
+//!
+//! Futures submit [`op::Op`]s with [`submit`], which queues them on the ring
+//! owned by the [`Runtime`]. Each tick polls every woken task, submits all
+//! SQEs queued meanwhile in one go, and then reaps CQEs, waking the tasks
+//! they belong to. When no task can make progress, it blocks in
+//! `io_uring_wait_cqe_nr` until a completion arrives. Wakers may be used
+//! from other threads, which then wake the runtime through an eventfd
+//! polled on the ring.
+//!
+//! ```ignore
+//! let rt = Runtime::new(IoUring::init(64)?)?;
+//! let (res, buf) = rt.block_on(async {
+//!     rt::submit(op::Read::new(fd, vec![0u8; 4096], 0)).await
+//! })?;
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::future::Future;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::ThreadId;
+
+use super::op::{Op, OpId, Ops};
+use super::*;
+
+pub mod cores;
+
+// Tokens are never this large, see `Token`.
+const MAIN_TASK: u64 = u64::MAX;
+// Ops always have `OPS_USER_DATA_TAG` set, so these are never mistaken for
+// one.
+const CANCEL_USER_DATA: u64 = 0;
+const WAKE_USER_DATA: u64 = 1;
+
+// Tasks woken since the last tick. Wakers may be sent to other threads, so
+// this is shared, and the runtime is woken through `eventfd` while it is
+// blocked in the ring.
+struct WakeQueue {
+    woken: Mutex<Vec<u64>>,
+    // Set while the runtime is blocked in the ring.
+    sleeping: AtomicBool,
+    eventfd: OwnedFd,
+    thread: ThreadId,
+}
+
+impl WakeQueue {
+    fn push(&self, task: u64) {
+        self.woken.lock().unwrap().push(task);
+        if self.sleeping.swap(false, Ordering::SeqCst) {
+            let one = 1u64;
+            unsafe {
+                libc::write(
+                    self.eventfd.as_raw_fd(),
+                    &one as *const u64 as *const libc::c_void,
+                    std::mem::size_of::<u64>(),
+                )
+            };
+        }
+    }
+
+    // Reset the eventfd after its poll fired.
+    fn drain_eventfd(&self) {
+        let mut count = 0u64;
+        unsafe {
+            libc::read(
+                self.eventfd.as_raw_fd(),
+                &mut count as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+    }
+}
+
+struct TaskWaker {
+    task: u64,
+    queue: Arc<WakeQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.queue.push(self.task);
+    }
+}
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+type CqeHandler = Box<dyn FnMut(&io_uring_cqe)>;
+
+struct Inner {
+    ring: RefCell<IoUring>,
+    ops: RefCell<Ops>,
+    // Tasks waiting on an op, keyed by the op's `user_data`.
+    waiting: RefCell<HashMap<u64, Waker>>,
+    // `None` while the task is being polled.
+    tasks: RefCell<Registry<Option<Task>>>,
+    queue: Arc<WakeQueue>,
+    // Whether a poll on `queue.eventfd` is in flight.
+    wake_armed: Cell<bool>,
+    // Called for CQEs that are not for an op, e.g., messages from other rings.
+    on_other_cqe: RefCell<Option<CqeHandler>>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<Inner>>> = const { RefCell::new(None) };
+}
+
+fn current() -> Rc<Inner> {
+    CURRENT
+        .with(|current| current.borrow().clone())
+        .expect("must be called from within Runtime::block_on")
+}
+
+impl Inner {
+    fn waker(&self, task: u64) -> Waker {
+        Waker::from(Arc::new(TaskWaker {
+            task,
+            queue: self.queue.clone(),
+        }))
+    }
+
+    fn poll_task(&self, token: Token) {
+        let Some(mut task) = self
+            .tasks
+            .borrow_mut()
+            .get_mut(token)
+            .and_then(Option::take)
+        else {
+            // Woken after it finished.
+            return;
+        };
+        let waker = self.waker(token.user_data());
+        if task
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_ready()
+        {
+            self.tasks.borrow_mut().remove(token);
+        } else if let Some(slot) = self.tasks.borrow_mut().get_mut(token) {
+            *slot = Some(task);
+        }
+    }
+
+    /// Submit queued SQEs and wake the tasks whose ops completed. Blocks for
+    /// at least one completion if `wait` is set.
+    fn reap(&self, wait: bool) -> std::io::Result<()> {
+        let mut ring = self.ring.borrow_mut();
+        if wait && !self.wake_armed.get() {
+            if ring.io_uring_sq_available() == 0 {
+                ring.submit();
+            }
+            if let Some(sqe) = ring.io_uring_get_sqe() {
+                sqe.io_uring_prep_poll_add(self.queue.eventfd.as_raw_fd(), PollEvents::IN)
+                    .set_sqe_data(WAKE_USER_DATA)
+                    .finalize();
+                self.wake_armed.set(true);
+            }
+        }
+        ring.submit();
+        let cqes = if wait && self.wake_armed.get() {
+            self.queue.sleeping.store(true, Ordering::SeqCst);
+            // Wakes since the last check did not see `sleeping`.
+            let cqes = if self.queue.woken.lock().unwrap().is_empty() {
+                io_uring_wait_cqe_nr(&mut ring, 1)
+            } else {
+                unsafe { io_uring_peek_cqe(&mut ring) }
+            };
+            self.queue.sleeping.store(false, Ordering::SeqCst);
+            cqes?
+        } else if wait {
+            io_uring_wait_cqe_nr(&mut ring, 1)?
+        } else {
+            unsafe { io_uring_peek_cqe(&mut ring) }?
+        };
+        let Some(mut cqes) = cqes else {
+            return Ok(());
+        };
+        let mut ops = self.ops.borrow_mut();
+        let mut waiting = self.waiting.borrow_mut();
+        let mut on_other_cqe = self.on_other_cqe.borrow_mut();
+        cqes.for_each_cqe(|cqe| {
+            // The CQE is from the ring the ops were pushed to.
+            if unsafe { ops.complete(cqe) } {
+                // The op only completes with its final CQE.
+                if cqe.expect_more_notifications() {
+                    return;
+                }
+                if let Some(waker) = waiting.remove(&cqe.user_data) {
+                    waker.wake();
+                }
+            } else if cqe.user_data == WAKE_USER_DATA {
+                self.queue.drain_eventfd();
+                self.wake_armed.set(false);
+            } else if cqe.user_data != CANCEL_USER_DATA {
+                if let Some(f) = on_other_cqe.as_mut() {
+                    f(cqe);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Owns an `IoUring` and runs futures on it, see the module docs.
+pub struct Runtime {
+    inner: Rc<Inner>,
+}
+
+impl Runtime {
+    pub fn new(ring: IoUring) -> std::io::Result<Runtime> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Runtime {
+            inner: Rc::new(Inner {
+                ring: RefCell::new(ring),
+                ops: RefCell::new(Ops::new()),
+                waiting: RefCell::new(HashMap::new()),
+                tasks: RefCell::new(Registry::new()),
+                on_other_cqe: RefCell::new(None),
+                wake_armed: Cell::new(false),
+                queue: Arc::new(WakeQueue {
+                    woken: Mutex::new(Vec::new()),
+                    sleeping: AtomicBool::new(false),
+                    eventfd: unsafe { OwnedFd::from_raw_fd(fd) },
+                    thread: std::thread::current().id(),
+                }),
+            }),
+        })
+    }
+
+    /// Run `future` to completion, along with any tasks it spawns. Tasks
+    /// still running when it completes are resumed by the next `block_on`.
+    ///
+    /// # Panics
+    /// If called from within another `block_on`, or from a thread other than
+    /// the one that created the runtime.
+    pub fn block_on<F: Future>(&self, future: F) -> std::io::Result<F::Output> {
+        assert_eq!(
+            std::thread::current().id(),
+            self.inner.queue.thread,
+            "runtime used from another thread"
+        );
+        CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            assert!(current.is_none(), "block_on called from within block_on");
+            *current = Some(self.inner.clone());
+        });
+        let _guard = ClearCurrent;
+
+        let mut future = std::pin::pin!(future);
+        let main_waker = self.inner.waker(MAIN_TASK);
+        let mut woken = vec![MAIN_TASK];
+        loop {
+            let mut tasks = std::mem::take(&mut woken).into_iter();
+            while let Some(task) = tasks.next() {
+                if task == MAIN_TASK {
+                    let mut cx = Context::from_waker(&main_waker);
+                    if let Poll::Ready(out) = future.as_mut().poll(&mut cx) {
+                        // Keep the other woken tasks for the next `block_on`,
+                        // as nothing may wake them again.
+                        let rest = tasks.filter(|task| *task != MAIN_TASK);
+                        self.inner.queue.woken.lock().unwrap().extend(rest);
+                        return Ok(out);
+                    }
+                } else {
+                    self.inner.poll_task(Token::from_user_data(task));
+                }
+            }
+            woken.append(&mut self.inner.queue.woken.lock().unwrap());
+            // Block in the ring even with no op in flight, as CQEs may be
+            // posted to it by other rings, e.g., with `IORING_OP_MSG_RING`.
+            self.inner.reap(woken.is_empty())?;
+            woken.append(&mut self.inner.queue.woken.lock().unwrap());
+        }
+    }
+}
+
+/// Have the current runtime call `f` for CQEs that are not for an op. `f`
+/// runs while CQEs are being reaped, so it may wake tasks but must not
+/// submit anything.
+///
+/// # Panics
+/// If called outside of `Runtime::block_on`.
+pub(crate) fn on_other_cqe(f: impl FnMut(&io_uring_cqe) + 'static) {
+    *current().on_other_cqe.borrow_mut() = Some(Box::new(f));
+}
+
+struct ClearCurrent;
+
+impl Drop for ClearCurrent {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.borrow_mut().take());
+    }
+}
+
+/// Run `future` as a separate task on the current runtime.
+///
+/// # Panics
+/// If called outside of `Runtime::block_on`.
+pub fn spawn<F: Future + 'static>(future: F) -> JoinHandle<F::Output> {
+    let inner = current();
+    let state = Rc::new(RefCell::new(JoinState {
+        output: None,
+        waker: None,
+    }));
+    let task_state = state.clone();
+    let task = async move {
+        let out = future.await;
+        let mut state = task_state.borrow_mut();
+        state.output = Some(out);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    };
+    let token = inner.tasks.borrow_mut().insert(Some(Box::pin(task)));
+    inner.queue.woken.lock().unwrap().push(token.user_data());
+    JoinHandle { state }
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Resolves to the output of a spawned task. Dropping it lets the task run
+/// on in the background.
+pub struct JoinHandle<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.borrow_mut();
+        match state.output.take() {
+            Some(out) => Poll::Ready(out),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Submit `op` to the current runtime's ring. The op is queued on first
+/// poll, and submitted with the rest of the tick's SQEs. Dropping the
+/// future before it completes cancels the op.
+///
+/// # Panics
+/// If polled outside of `Runtime::block_on`.
+pub fn submit<O: Op>(op: O) -> OpFuture<O> {
+    OpFuture {
+        state: OpState::Unsubmitted(op),
+        rt: Weak::new(),
+    }
+}
+
+enum OpState<O: Op> {
+    Unsubmitted(O),
+    InFlight(OpId<O>),
+    Done,
+}
+
+/// The future returned by [`submit`].
+pub struct OpFuture<O: Op> {
+    state: OpState<O>,
+    // The runtime the op was submitted to, weak as tasks own their futures.
+    rt: Weak<Inner>,
+}
+
+// Nothing is pinned, the op is moved into `Ops` when submitted.
+impl<O: Op> Unpin for OpFuture<O> {}
+
+impl<O: Op> Future for OpFuture<O> {
+    type Output = O::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<O::Output> {
+        let inner = current();
+        let id = match std::mem::replace(&mut self.state, OpState::Done) {
+            OpState::Unsubmitted(op) => {
+                let mut ring = inner.ring.borrow_mut();
+                if ring.io_uring_sq_available() == 0 {
+                    ring.submit();
+                }
+                match inner.ops.borrow_mut().push(&mut ring, op) {
+                    Ok(id) => {
+                        self.rt = Rc::downgrade(&inner);
+                        id
+                    }
+                    Err(op) => {
+                        // The SQ is still full, try again next tick.
+                        self.state = OpState::Unsubmitted(op);
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
+                    }
+                }
+            }
+            OpState::InFlight(id) => id,
+            OpState::Done => panic!("OpFuture polled after completion"),
+        };
+        let taken = inner.ops.borrow_mut().take(id);
+        match taken {
+            Ok(out) => Poll::Ready(out),
+            Err(id) => {
+                inner
+                    .waiting
+                    .borrow_mut()
+                    .insert(id.user_data(), cx.waker().clone());
+                self.state = OpState::InFlight(id);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<O: Op> Drop for OpFuture<O> {
+    fn drop(&mut self) {
+        let OpState::InFlight(id) = std::mem::replace(&mut self.state, OpState::Done) else {
+            return;
+        };
+        // The runtime is being dropped, and leaks the op along with `Ops`.
+        let Some(inner) = self.rt.upgrade() else {
+            return;
+        };
+        let user_data = id.user_data();
+        inner.waiting.borrow_mut().remove(&user_data);
+        if inner.ops.borrow_mut().detach(id) {
+            let mut ring = inner.ring.borrow_mut();
+            if ring.io_uring_sq_available() == 0 {
+                ring.submit();
+            }
+            // Without an SQE the op still completes in time, and is then
+            // dropped by `Ops`.
+            if let Some(sqe) = ring.io_uring_get_sqe() {
+                sqe.io_uring_prep_cancel(user_data, 0)
+                    .set_sqe_data(CANCEL_USER_DATA)
+                    .finalize();
+            }
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! A thread-per-core runtime: one thread pinned to each core, each running
+//! its own [`Runtime`] on its own ring, with a fixed file table through which
+//! connections are handed between cores using `IORING_OP_MSG_RING`.
+//!
+//! ```ignore
+//! Cores::start(4, 1024, |_| IoUring::init(256), |core| async move {
+//!     if core == 0 {
+//!         // Accept on core 0 and spread connections over the others.
+//!         for next in (1..cores::count()).cycle() {
+//!             let conn = cores::accept(listener_fd).await.unwrap();
+//!             cores::send_fd(next, conn).await.unwrap();
+//!         }
+//!     } else {
+//!         loop {
+//!             let conn = cores::recv_fd().await;
+//!             rt::spawn(serve(conn));
+//!         }
+//!     }
+//! })?
+//! .join()?;
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::{poll_fn, Future};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::thread::JoinHandle;
+
+use crate::op::{self, MsgRing, SendFixedFd};
+use crate::rt::{self, Runtime};
+use crate::*;
+
+// User data of the CQEs we post to other cores' rings. These clash with
+// neither ops nor the runtime's own requests.
+const JOBS_USER_DATA: u64 = 1 << 62;
+const FD_USER_DATA: u64 = 1 << 62 | 1;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Shared {
+    // Duplicates of each core's ring FD, which keep the rings alive so that
+    // sending to a core that has exited is harmless.
+    rings: Vec<OwnedFd>,
+    jobs: Vec<Mutex<Vec<Job>>>,
+}
+
+// Set by the handler for CQEs from other cores, waking whoever waits on it.
+// Idle cores block in their ring, so these CQEs always get through.
+#[derive(Default)]
+struct Signal {
+    set: Cell<bool>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl Signal {
+    fn notify(&self) {
+        self.set.set(true);
+        if let Some(waker) = self.waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+
+    async fn wait(&self) {
+        poll_fn(|cx| {
+            if self.set.replace(false) {
+                Poll::Ready(())
+            } else {
+                *self.waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+struct Local {
+    id: usize,
+    shared: Arc<Shared>,
+    files: FixedFiles,
+    jobs: Signal,
+    conns: RefCell<VecDeque<FixedFd>>,
+    conns_ready: Signal,
+}
+
+thread_local! {
+    static LOCAL: RefCell<Option<Rc<Local>>> = const { RefCell::new(None) };
+}
+
+fn local() -> Rc<Local> {
+    LOCAL
+        .with(|local| local.borrow().clone())
+        .expect("must be called on a core started by Cores::start")
+}
+
+impl Local {
+    fn on_cqe(&self, cqe: &io_uring_cqe) {
+        match cqe.user_data {
+            JOBS_USER_DATA => self.jobs.notify(),
+            FD_USER_DATA => {
+                let Ok(slot) = cqe.result() else {
+                    return;
+                };
+                match self.files.adopt(slot) {
+                    Some(fd) => {
+                        self.conns.borrow_mut().push_back(fd);
+                        self.conns_ready.notify();
+                    }
+                    // The kernel installed the file, so close it rather
+                    // than leak the slot.
+                    None => {
+                        let _ = self.files.update(slot, &[-1]);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn run_jobs(&self) {
+        loop {
+            self.jobs.wait().await;
+            let jobs = std::mem::take(&mut *self.shared.jobs[self.id].lock().unwrap());
+            for job in jobs {
+                job();
+            }
+        }
+    }
+}
+
+fn pin_to_cpu(cpu: usize) -> std::io::Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    unsafe { libc::CPU_SET(cpu, &mut set) };
+    let ret = unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+    if ret < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn dup_fd(fd: RawFd) -> std::io::Result<OwnedFd> {
+    let fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if fd < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+/// The threads started by [`Cores::start`].
+pub struct Cores {
+    threads: Vec<JoinHandle<std::io::Result<()>>>,
+}
+
+impl Cores {
+    /// Start `n` threads pinned to CPUs `0..n` (wrapping around if there are
+    /// fewer), each with a ring from `make_ring` that has a sparse fixed
+    /// file table of `files` slots registered, running `main(core)`.
+    ///
+    /// The whole file table is left for the kernel to allocate from, as done
+    /// by [`accept`] and [`recv_fd`].
+    pub fn start<R, F, Fut>(n: usize, files: u32, make_ring: R, main: F) -> std::io::Result<Cores>
+    where
+        R: Fn(usize) -> std::io::Result<IoUring> + Send + Sync + 'static,
+        F: Fn(usize) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let make_ring = Arc::new(make_ring);
+        let main = Arc::new(main);
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let mut go = Vec::with_capacity(n);
+        let mut threads = Vec::with_capacity(n);
+        for id in 0..n {
+            let (go_tx, go_rx) = mpsc::channel::<Option<Arc<Shared>>>();
+            go.push(go_tx);
+            let ready_tx = ready_tx.clone();
+            let make_ring = make_ring.clone();
+            let main = main.clone();
+            let thread = std::thread::Builder::new()
+                .name(format!("core-{}", id))
+                .spawn(move || {
+                    let setup = (|| {
+                        pin_to_cpu(id % cpus)?;
+                        let mut ring = make_ring(id)?;
+                        let table = ring.register_files_sparse(files)?;
+                        table.set_alloc_range(0, files)?;
+                        let ring_fd = dup_fd(ring.as_raw_fd())?;
+                        Ok((ring, table, ring_fd))
+                    })();
+                    let (ring, table) = match setup {
+                        Ok((ring, table, ring_fd)) => {
+                            let _ = ready_tx.send((id, Ok(ring_fd)));
+                            (ring, table)
+                        }
+                        Err(e) => {
+                            let _ = ready_tx.send((id, Err(e)));
+                            return Ok(());
+                        }
+                    };
+                    drop(ready_tx);
+                    // Wait for every core to have its ring before starting.
+                    let Ok(Some(shared)) = go_rx.recv() else {
+                        return Ok(());
+                    };
+                    run_core(id, shared, ring, table, main(id))
+                })?;
+            threads.push(thread);
+        }
+        drop(ready_tx);
+
+        let mut rings: Vec<Option<OwnedFd>> = (0..n).map(|_| None).collect();
+        let mut error = None;
+        for (id, ring) in ready_rx {
+            match ring {
+                Ok(fd) => rings[id] = Some(fd),
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        if error.is_none() && rings.iter().any(Option::is_none) {
+            error = Some(std::io::Error::other("core panicked during setup"));
+        }
+        if let Some(e) = error {
+            drop(go);
+            for thread in threads {
+                let _ = thread.join();
+            }
+            return Err(e);
+        }
+        let shared = Arc::new(Shared {
+            rings: rings.into_iter().map(Option::unwrap).collect(),
+            jobs: (0..n).map(|_| Mutex::new(Vec::new())).collect(),
+        });
+        for go in go {
+            let _ = go.send(Some(shared.clone()));
+        }
+        Ok(Cores { threads })
+    }
+
+    /// Wait for all cores to finish, returning the first error.
+    ///
+    /// # Panics
+    /// If a core panicked.
+    pub fn join(self) -> std::io::Result<()> {
+        let mut result = Ok(());
+        for thread in self.threads {
+            let ret = thread.join().expect("core panicked");
+            result = result.and(ret);
+        }
+        result
+    }
+}
+
+fn run_core<Fut: Future<Output = ()>>(
+    id: usize,
+    shared: Arc<Shared>,
+    ring: IoUring,
+    files: FixedFiles,
+    main: Fut,
+) -> std::io::Result<()> {
+    let local = Rc::new(Local {
+        id,
+        shared,
+        files,
+        jobs: Signal::default(),
+        conns: RefCell::new(VecDeque::new()),
+        conns_ready: Signal::default(),
+    });
+    LOCAL.with(|l| *l.borrow_mut() = Some(local.clone()));
+    let rt = Runtime::new(ring)?;
+    let ret = rt.block_on(async move {
+        let handler = local.clone();
+        rt::on_other_cqe(move |cqe| handler.on_cqe(cqe));
+        rt::spawn(async move { local.run_jobs().await });
+        main.await
+    });
+    LOCAL.with(|l| l.borrow_mut().take());
+    ret
+}
+
+/// Returns the current core's index.
+///
+/// # Panics
+/// If not called on a core.
+pub fn id() -> usize {
+    local().id
+}
+
+/// Returns the number of cores.
+///
+/// # Panics
+/// If not called on a core.
+pub fn count() -> usize {
+    local().shared.rings.len()
+}
+
+/// Run `job` on `core`, e.g., to spawn a task there. Completes once the
+/// core has been notified.
+///
+/// # Panics
+/// If not called on a core, or `core` is out of range.
+pub async fn send(core: usize, job: impl FnOnce() + Send + 'static) -> std::io::Result<()> {
+    let local = local();
+    local.shared.jobs[core].lock().unwrap().push(Box::new(job));
+    let ring_fd = local.shared.rings[core].as_raw_fd();
+    rt::submit(MsgRing::new(ring_fd, 0, JOBS_USER_DATA)).await
+}
+
+/// Move `fd` from our fixed file table to that of `core`, where it is
+/// picked up with [`recv_fd`]. Connections that are normal FDs can instead
+/// simply be moved along with a job passed to [`send`].
+///
+/// # Panics
+/// If not called on a core, or `core` is out of range.
+pub async fn send_fd(core: usize, fd: FixedFd) -> std::io::Result<()> {
+    let ring_fd = local().shared.rings[core].as_raw_fd();
+    rt::submit(SendFixedFd::new(ring_fd, fd, FD_USER_DATA)).await
+}
+
+/// Wait for a fixed file sent to this core with [`send_fd`].
+///
+/// # Panics
+/// If not called on a core.
+pub async fn recv_fd() -> FixedFd {
+    let local = local();
+    loop {
+        if let Some(fd) = local.conns.borrow_mut().pop_front() {
+            return fd;
+        }
+        local.conns_ready.wait().await;
+    }
+}
+
+/// Accept a connection on `fd` straight into this core's fixed file table.
+///
+/// # Panics
+/// If not called on a core.
+pub async fn accept(fd: RawFd) -> std::io::Result<FixedFd> {
+    let slot = rt::submit(op::AcceptDirect::new(fd, 0)).await?;
+    local()
+        .files
+        .adopt(slot)
+        .ok_or_else(|| std::io::Error::from_raw_os_error(libc::EBADF))
+}
@@ -0,0 +1,112 @@
+use std::mem::size_of;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::os::fd::RawFd;
+
+/// A socket address in the form the kernel expects.
+pub(crate) struct SockAddrBuf {
+    pub(crate) storage: libc::sockaddr_storage,
+    pub(crate) len: libc::socklen_t,
+}
+
+impl Default for SockAddrBuf {
+    fn default() -> Self {
+        SockAddrBuf {
+            storage: unsafe { std::mem::zeroed() },
+            len: size_of::<libc::sockaddr_storage>() as libc::socklen_t,
+        }
+    }
+}
+
+impl SockAddrBuf {
+    pub(crate) fn as_ptr(&self) -> *const libc::sockaddr {
+        &self.storage as *const libc::sockaddr_storage as *const libc::sockaddr
+    }
+
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut libc::sockaddr {
+        &mut self.storage as *mut libc::sockaddr_storage as *mut libc::sockaddr
+    }
+
+    /// Convert an address filled in by the kernel, returning `None` for
+    /// families other than `AF_INET` and `AF_INET6`.
+    pub(crate) fn to_socket_addr(&self) -> Option<SocketAddr> {
+        let ptr = &self.storage as *const libc::sockaddr_storage;
+        match self.storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let sin = unsafe { &*(ptr as *const libc::sockaddr_in) };
+                Some(SocketAddr::V4(SocketAddrV4::new(
+                    Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)),
+                    u16::from_be(sin.sin_port),
+                )))
+            }
+            libc::AF_INET6 => {
+                let sin6 = unsafe { &*(ptr as *const libc::sockaddr_in6) };
+                Some(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                    u16::from_be(sin6.sin6_port),
+                    sin6.sin6_flowinfo,
+                    sin6.sin6_scope_id,
+                )))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<&SocketAddr> for SockAddrBuf {
+    fn from(addr: &SocketAddr) -> Self {
+        let mut buf = SockAddrBuf::default();
+        let ptr = &mut buf.storage as *mut libc::sockaddr_storage;
+        match addr {
+            SocketAddr::V4(a) => {
+                let sin = unsafe { &mut *(ptr as *mut libc::sockaddr_in) };
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = a.port().to_be();
+                sin.sin_addr.s_addr = u32::from(*a.ip()).to_be();
+                buf.len = size_of::<libc::sockaddr_in>() as libc::socklen_t;
+            }
+            SocketAddr::V6(a) => {
+                let sin6 = unsafe { &mut *(ptr as *mut libc::sockaddr_in6) };
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_port = a.port().to_be();
+                sin6.sin6_flowinfo = a.flowinfo();
+                sin6.sin6_addr.s6_addr = a.ip().octets();
+                sin6.sin6_scope_id = a.scope_id();
+                buf.len = size_of::<libc::sockaddr_in6>() as libc::socklen_t;
+            }
+        }
+        buf
+    }
+}
+
+/// Bind `fd` to `addr` and start listening, setting `SO_REUSEADDR` first
+/// as `std::net::TcpListener` does.
+///
+/// Before Linux 6.11 there are no `bind` or `listen` opcodes, so this is a
+/// blocking fallback for sockets created with `io_uring_prep_socket`. Neither
+/// call waits on the network, so this is cheap. Where the probe supports
+/// `Opcode::Bind` and `Opcode::Listen`, `io_uring_prep_bind` and
+/// `io_uring_prep_listen` can be used instead, after setting any socket
+/// options.
+pub fn bind_and_listen(fd: RawFd, addr: &SocketAddr, backlog: i32) -> std::io::Result<()> {
+    let addr = SockAddrBuf::from(addr);
+    let one: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_REUSEADDR,
+            &one as *const libc::c_int as *const libc::c_void,
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if unsafe { libc::bind(fd, addr.as_ptr(), addr.len) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if unsafe { libc::listen(fd, backlog) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
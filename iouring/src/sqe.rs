@@ -1,21 +1,138 @@
-use std::{marker::PhantomData, os::fd::RawFd};
+use std::{
+    ffi::{CStr, CString},
+    marker::PhantomData,
+    net::SocketAddr,
+    os::fd::RawFd,
+    time::Duration,
+};
 
 use super::*;
 
+// Not in older kernel headers, requires 6.4.
+const IORING_TIMEOUT_MULTISHOT: u32 = 1 << 6;
+// Not in older kernel headers, require 6.11.
+const IORING_OP_BIND: io_uring_op = 56;
+const IORING_OP_LISTEN: io_uring_op = 57;
+
+/// Arguments that the kernel reads when it consumes a SQE, rather than
+/// when the request completes. Each SQ slot has its own `SqeArgs`, which is
+/// only reused once the slot is handed out again, by which point the kernel
+/// has consumed the previous SQE (and, given `IORING_FEAT_SUBMIT_STABLE`,
+/// any data it needs later has been copied).
+#[derive(Default)]
+pub(crate) struct SqeArgs {
+    ts: __kernel_timespec,
+    paths: [Option<CString>; 2],
+    how: open_how,
+    addr: SockAddrBuf,
+}
+
+/// The result of an `op::Statx`. The `statx` struct is boxed, so it stays in
+/// place if the op is moved while in flight.
+pub struct Statx(pub(crate) Box<libc::statx>);
+
+impl Statx {
+    pub(crate) fn new() -> Statx {
+        Statx(Box::new(unsafe { std::mem::zeroed() }))
+    }
+
+    pub fn get(&self) -> &libc::statx {
+        &self.0
+    }
+}
+
+/// How `io_uring_prep_renameat` treats an existing destination.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenameFlags {
+    /// Replace the destination if it exists.
+    Replace = 0,
+    /// Fail with `EEXIST` if the destination exists.
+    NoReplace = libc::RENAME_NOREPLACE,
+    /// Atomically exchange source and destination, both of which must exist.
+    Exchange = libc::RENAME_EXCHANGE,
+}
+
+/// What `io_uring_prep_unlinkat` removes.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnlinkFlags {
+    /// Remove a file, like `unlink`.
+    File = 0,
+    /// Remove an empty directory, like `rmdir`.
+    RemoveDir = libc::AT_REMOVEDIR as u32,
+}
+
+bitset! {
+    /// A set of `poll(2)` events, used both to request events when polling
+    /// and to report the events that fired.
+    pub struct PollEvents {
+        IN = libc::POLLIN as u32,
+        PRI = libc::POLLPRI as u32,
+        OUT = libc::POLLOUT as u32,
+        ERR = libc::POLLERR as u32,
+        HUP = libc::POLLHUP as u32,
+        NVAL = libc::POLLNVAL as u32,
+        RDHUP = libc::POLLRDHUP as u32,
+    }
+}
+
 /// Rust friendly representation of a SQE
 pub struct Sqe<'a> {
     sqe: *mut io_uring_sqe,
+    args: *mut SqeArgs,
+    // Set for rings with `IORING_SETUP_SQE128`.
+    big: bool,
     _phantom: PhantomData<&'a ()>,
 }
 
 impl Sqe<'_> {
-    pub(crate) unsafe fn init<'a>(sqe: *mut io_uring_sqe) -> Sqe<'a> {
+    pub(crate) unsafe fn init<'a>(
+        sqe: *mut io_uring_sqe,
+        args: *mut SqeArgs,
+        big: bool,
+    ) -> Sqe<'a> {
         Sqe {
             sqe,
+            args,
+            big,
             _phantom: Default::default(),
         }
     }
 
+    /// Store `ts` in this SQE's slot, returning the address the kernel
+    /// should read it from.
+    fn stash_timespec(&self, ts: Duration) -> u64 {
+        let args = unsafe { &mut (*self.args) };
+        args.ts = __kernel_timespec {
+            tv_sec: ts.as_secs() as _,
+            tv_nsec: ts.subsec_nanos() as _,
+        };
+        &args.ts as *const __kernel_timespec as u64
+    }
+
+    /// Store a copy of `path` in this SQE's slot (at `idx`, since some
+    /// operations take two paths), returning the address the kernel
+    /// should read it from.
+    fn stash_path(&self, idx: usize, path: &CStr) -> usize {
+        let args = unsafe { &mut (*self.args) };
+        args.paths[idx].insert(path.to_owned()).as_ptr() as usize
+    }
+
+    /// Have the operation install the file it creates in the fixed file
+    /// table at `file_index`, rather than returning a normal FD.
+    fn set_target_fixed_file(self, file_index: u32) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        // The kernel uses 0 to mean "not direct", so slots are 1-based,
+        // other than the value asking the kernel to pick a slot.
+        sqe.__bindgen_anon_5.file_index = if file_index == IORING_FILE_INDEX_ALLOC {
+            IORING_FILE_INDEX_ALLOC
+        } else {
+            file_index + 1
+        };
+        self
+    }
+
     /// Get raw SQE pointer.
     ///
     /// # Safety
@@ -25,6 +142,38 @@ impl Sqe<'_> {
         self.sqe
     }
 
+    /// Returns the command area at the end of the SQE, where
+    /// `IORING_OP_URING_CMD` passes its command to the driver. This is 16
+    /// bytes, or 80 for big SQEs in rings set up with `IORING_SETUP_SQE128`.
+    pub fn cmd(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.cmd_ptr(), self.cmd_len()) }
+    }
+
+    /// Mutable version of [`Sqe::cmd`].
+    pub fn cmd_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.cmd_ptr(), self.cmd_len()) }
+    }
+
+    /// Whether this is a 128 byte SQE, see [`Sqe::cmd`].
+    pub fn is_big(&self) -> bool {
+        self.big
+    }
+
+    fn cmd_ptr(&self) -> *mut u8 {
+        let offset = std::mem::offset_of!(io_uring_sqe, __bindgen_anon_6);
+        unsafe { (self.sqe as *mut u8).add(offset) }
+    }
+
+    fn cmd_len(&self) -> usize {
+        let len = std::mem::size_of::<io_uring_sqe>()
+            - std::mem::offset_of!(io_uring_sqe, __bindgen_anon_6);
+        if self.big {
+            len + std::mem::size_of::<io_uring_sqe>()
+        } else {
+            len
+        }
+    }
+
     /// Set SQE data, this shows up in the corresponding CQE allowing
     /// returns to be correlated with requests.
     pub fn set_sqe_data(self, data: u64) -> Self {
@@ -147,6 +296,14 @@ impl Sqe<'_> {
         self
     }
 
+    // Receives use a different bit than accepts, which for them means
+    // `IORING_RECVSEND_POLL_FIRST`.
+    fn set_recv_multishot(self) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        sqe.ioprio |= IORING_RECV_MULTISHOT as u16;
+        self
+    }
+
     pub fn io_uring_prep_multishot_accept(
         self,
         fd: i32,
@@ -158,6 +315,34 @@ impl Sqe<'_> {
             .set_multishot()
     }
 
+    /// Prepare an accept that installs the connection in the fixed file table
+    /// at `file_index` rather than returning a FD. Pass `IORING_FILE_INDEX_ALLOC`
+    /// to have the kernel pick a free slot, which is returned as the CQE result.
+    pub fn io_uring_prep_accept_direct(
+        self,
+        fd: i32,
+        addr: *mut libc::sockaddr,
+        len: *mut libc::socklen_t,
+        flags: u32,
+        file_index: u32,
+    ) -> Self {
+        self.io_uring_prep_accept(fd, addr, len, flags)
+            .set_target_fixed_file(file_index)
+    }
+
+    /// Prepare a multishot accept that installs each connection in a free
+    /// slot of the fixed file table, returned as the CQE result.
+    pub fn io_uring_prep_multishot_accept_direct(
+        self,
+        fd: i32,
+        addr: *mut libc::sockaddr,
+        len: *mut libc::socklen_t,
+        flags: u32,
+    ) -> Self {
+        self.io_uring_prep_multishot_accept(fd, addr, len, flags)
+            .set_target_fixed_file(IORING_FILE_INDEX_ALLOC)
+    }
+
     /// Prepare a splice command. Either `fd_in` or `fd_out` must be a pipe.
     /// If `fd_in` is a pipe, `off_in` must be set to -1.
     ///
@@ -236,32 +421,28 @@ impl Sqe<'_> {
         s
     }
 
-    /// Prepare a read operation that uses a fixed buffer.
-    /// The space pointed to by `buf` must have been registered
-    /// at index `buf_index`. `buf` need not point to the start of
-    /// the registed buffer, it merely needs to be within the registered
-    /// region.
+    /// Prepare a read into the fixed buffer `buf`, of up to its capacity.
+    /// Once the read completes, set the length of `buf` from the result
+    /// using `FixedBuf::set_len`. See `op::ReadFixed` for a safe version.
     ///
     /// # Safety
-    /// `buf` must have been previously registered (using `io_uring_register_buffers`) at `buf_index`.
-    pub unsafe fn io_uring_prep_read_fixed<T>(
+    /// `buf` must not be dropped or accessed until the read completes.
+    pub unsafe fn io_uring_prep_read_fixed(
         self,
         fd: RawFd,
-        buf: NonNull<T>,
-        nbytes: usize,
+        buf: &mut FixedBuf,
         offset: u64,
-        buf_index: u16,
     ) -> Self {
         let sqe = &mut (*self.sqe);
         Self::io_uring_prep_rw(
             sqe,
             IORING_OP_READ_FIXED,
             fd,
-            buf.addr().get(),
-            nbytes as u32,
+            buf.as_ptr() as usize,
+            buf.capacity() as u32,
             offset,
         );
-        (*sqe).__bindgen_anon_4.buf_index = buf_index;
+        sqe.__bindgen_anon_4.buf_index = buf.buf_index();
         self
     }
 
@@ -300,32 +481,22 @@ impl Sqe<'_> {
         s
     }
 
-    /// Prepare a write operation that uses a fixed buffer.
-    /// The space pointed to by `buf` must have been registered
-    /// at index `buf_index`. `buf` need not point to the start of
-    /// the registed buffer, it merely needs to be within the registered
-    /// region.
+    /// Prepare a write of the contents of the fixed buffer `buf`. See
+    /// `op::WriteFixed` for a safe version.
     ///
     /// # Safety
-    /// `buf` must have been previously registered at `buf_index`.
-    pub unsafe fn io_uring_prep_write_fixed<T>(
-        self,
-        fd: RawFd,
-        buf: NonNull<T>,
-        nbytes: usize,
-        offset: u64,
-        buf_index: u16,
-    ) -> Self {
+    /// `buf` must not be dropped or modified until the write completes.
+    pub unsafe fn io_uring_prep_write_fixed(self, fd: RawFd, buf: &FixedBuf, offset: u64) -> Self {
         let sqe = &mut (*self.sqe);
         Self::io_uring_prep_rw(
             sqe,
             IORING_OP_WRITE_FIXED,
             fd,
-            buf.addr().get(),
-            nbytes as u32,
+            buf.as_ptr() as usize,
+            buf.len() as u32,
             offset,
         );
-        (*sqe).__bindgen_anon_4.buf_index = buf_index as u16;
+        sqe.__bindgen_anon_4.buf_index = buf.buf_index();
         self
     }
 
@@ -355,7 +526,8 @@ impl Sqe<'_> {
         msg: NonNull<libc::msghdr>,
         flags: u32,
     ) -> Self {
-        self.io_uring_prep_recvmsg(fd, msg, flags).set_multishot()
+        self.io_uring_prep_recvmsg(fd, msg, flags)
+            .set_recv_multishot()
     }
 
     /// Post a `sendmsg` request.
@@ -395,6 +567,60 @@ impl Sqe<'_> {
         self
     }
 
+    #[inline(always)]
+    fn set_poll_mask(sqe: &mut io_uring_sqe, events: PollEvents) {
+        // The kernel reads the 32 bit mask word-swapped on big endian.
+        #[cfg(target_endian = "big")]
+        let mask = events.bits().rotate_left(16);
+        #[cfg(target_endian = "little")]
+        let mask = events.bits();
+        sqe.__bindgen_anon_3.poll32_events = mask;
+    }
+
+    /// Prepare a one shot poll of `fd` for `events`. The CQE result is the
+    /// set of events that fired, see `io_uring_cqe::poll_events`.
+    pub fn io_uring_prep_poll_add(self, fd: RawFd, events: PollEvents) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_POLL_ADD, fd, 0, 0, 0) };
+        Self::set_poll_mask(sqe, events);
+        self
+    }
+
+    /// Prepare a multishot poll of `fd`, which posts a CQE each time one of
+    /// `events` fires until it is removed or fails. Every CQE except the last
+    /// has `expect_more_notifications` set.
+    pub fn io_uring_prep_poll_multishot(self, fd: RawFd, events: PollEvents) -> Self {
+        let s = self.io_uring_prep_poll_add(fd, events);
+        unsafe { (*s.sqe).len = IORING_POLL_ADD_MULTI };
+        s
+    }
+
+    /// Remove the poll request identified by `user_data`.
+    pub fn io_uring_prep_poll_remove(self, user_data: u64) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_POLL_REMOVE, -1, 0, 0, 0) };
+        sqe.__bindgen_anon_2.addr = user_data;
+        self
+    }
+
+    /// Update the poll request identified by `old_user_data`. `flags` select
+    /// what is updated: `IORING_POLL_UPDATE_EVENTS` to replace its events with
+    /// `events`, and `IORING_POLL_UPDATE_USER_DATA` to replace its user data
+    /// with `new_user_data`. `IORING_POLL_ADD_MULTI` makes it multishot.
+    pub fn io_uring_prep_poll_update(
+        self,
+        old_user_data: u64,
+        new_user_data: u64,
+        events: PollEvents,
+        flags: u32,
+    ) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_POLL_REMOVE, -1, 0, flags, new_user_data) };
+        sqe.__bindgen_anon_2.addr = old_user_data;
+        Self::set_poll_mask(sqe, events);
+        self
+    }
+
     /// Prepare fsync.
     pub fn io_uring_prep_fsync(self, fd: RawFd, fsync_flag: u32) -> Self {
         let sqe = unsafe { &mut (*self.sqe) };
@@ -403,7 +629,46 @@ impl Sqe<'_> {
         self
     }
 
-    // Missing prep_timeout, prep_timeout_remove, prep_timeout_update
+    /// Prepare a timeout that completes once `ts` has elapsed or, if `count`
+    /// is not zero, once `count` other requests have completed, whichever
+    /// happens first. An expired timeout completes with `-ETIME`.
+    ///
+    /// `flags` are `IORING_TIMEOUT_*` flags, e.g., `IORING_TIMEOUT_ABS`
+    /// to treat `ts` as an absolute time on the selected clock.
+    pub fn io_uring_prep_timeout(self, ts: Duration, count: u32, flags: u32) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        let ts = self.stash_timespec(ts);
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_TIMEOUT, -1, ts as usize, 1, count as u64) };
+        sqe.__bindgen_anon_3.timeout_flags = flags;
+        self
+    }
+
+    /// Prepare a timeout that fires every `ts`, stopping after `count`
+    /// expirations or never if `count` is zero. Every expiration except the
+    /// last has `expect_more_notifications` set.
+    pub fn io_uring_prep_timeout_multishot(self, ts: Duration, count: u32, flags: u32) -> Self {
+        self.io_uring_prep_timeout(ts, count, flags | IORING_TIMEOUT_MULTISHOT)
+    }
+
+    /// Remove the timeout identified by `user_data`.
+    pub fn io_uring_prep_timeout_remove(self, user_data: u64, flags: u32) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_TIMEOUT_REMOVE, -1, 0, 0, 0) };
+        sqe.__bindgen_anon_2.addr = user_data;
+        sqe.__bindgen_anon_3.timeout_flags = flags;
+        self
+    }
+
+    /// Change the expiration of the timeout identified by `user_data` to `ts`.
+    /// Pass `IORING_LINK_TIMEOUT_UPDATE` in `flags` to update a link timeout.
+    pub fn io_uring_prep_timeout_update(self, user_data: u64, ts: Duration, flags: u32) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        let ts = self.stash_timespec(ts);
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_TIMEOUT_REMOVE, -1, 0, 0, ts) };
+        sqe.__bindgen_anon_2.addr = user_data;
+        sqe.__bindgen_anon_3.timeout_flags = flags | IORING_TIMEOUT_UPDATE;
+        self
+    }
 
     /// Cancel SQE identified by `user_data`
     pub fn io_uring_prep_cancel(self, user_data: u64, flags: u32) -> Self {
@@ -414,7 +679,17 @@ impl Sqe<'_> {
         self
     }
 
-    // Missing prep_link_timeout, which allows a linked operation to be cancelled.
+    /// Prepare a timeout for the previous SQE, which must have been marked
+    /// with `set_link`. If the linked request has not completed after `ts`
+    /// it is cancelled, and this completes with `-ETIME`.
+    pub fn io_uring_prep_link_timeout(self, ts: Duration, flags: u32) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        let ts = self.stash_timespec(ts);
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_LINK_TIMEOUT, -1, ts as usize, 1, 0) };
+        sqe.__bindgen_anon_3.timeout_flags = flags;
+        self
+    }
+
     pub fn io_uring_prep_connect(
         self,
         fd: RawFd,
@@ -428,8 +703,297 @@ impl Sqe<'_> {
         self
     }
 
+    /// Prepare a connect of `fd` to `addr`. The address is copied, and so
+    /// need not outlive this call.
+    pub fn io_uring_prep_connect_addr(self, fd: RawFd, addr: &SocketAddr) -> Self {
+        let (ptr, len) = unsafe {
+            (*self.args).addr = SockAddrBuf::from(addr);
+            ((*self.args).addr.as_ptr(), (*self.args).addr.len)
+        };
+        self.io_uring_prep_connect(fd, ptr, len)
+    }
+
+    /// Prepare a bind of `fd` to `addr`, which is copied. Requires Linux
+    /// 6.11, see `Opcode::Bind`.
+    pub fn io_uring_prep_bind(self, fd: RawFd, addr: &SocketAddr) -> Self {
+        let (ptr, len) = unsafe {
+            (*self.args).addr = SockAddrBuf::from(addr);
+            ((*self.args).addr.as_ptr(), (*self.args).addr.len)
+        };
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_BIND, fd, ptr as usize, 0, len as u64) };
+        self
+    }
+
+    /// Prepare a listen on `fd`, as with `listen(fd, backlog)`. Requires
+    /// Linux 6.11, see `Opcode::Listen`.
+    pub fn io_uring_prep_listen(self, fd: RawFd, backlog: u32) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_LISTEN, fd, 0, backlog, 0) };
+        self
+    }
+
+    /// Prepare creation of a socket, as with `socket(domain, ty, protocol)`.
+    /// The CQE result is the new FD. `flags` are currently unused by the
+    /// kernel and must be 0.
+    pub fn io_uring_prep_socket(self, domain: i32, ty: i32, protocol: i32, flags: u32) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe {
+            Self::io_uring_prep_rw(sqe, IORING_OP_SOCKET, domain, 0, protocol as u32, ty as u64)
+        };
+        sqe.__bindgen_anon_3.rw_flags = flags as i32;
+        self
+    }
+
+    /// Prepare creation of a socket that is installed in the fixed file
+    /// table at `file_index` rather than returned as a FD. Pass
+    /// `IORING_FILE_INDEX_ALLOC` to have the kernel pick a free slot,
+    /// which is returned as the CQE result.
+    pub fn io_uring_prep_socket_direct(
+        self,
+        domain: i32,
+        ty: i32,
+        protocol: i32,
+        file_index: u32,
+        flags: u32,
+    ) -> Self {
+        self.io_uring_prep_socket(domain, ty, protocol, flags)
+            .set_target_fixed_file(file_index)
+    }
+
+    /// Prepare a `shutdown` of `fd`, with `how` being one of `libc::SHUT_*`.
+    pub fn io_uring_prep_shutdown(self, fd: RawFd, how: i32) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_SHUTDOWN, fd, 0, how as u32, 0) };
+        self
+    }
+
+    /// Post a CQE to the ring `ring_fd`, with `len` as its result and `data`
+    /// as its user data. This completes on our ring once the CQE is posted,
+    /// and can be used to wake a thread waiting on the other ring.
+    pub fn io_uring_prep_msg_ring(self, ring_fd: RawFd, len: u32, data: u64, flags: u32) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe {
+            Self::io_uring_prep_rw(
+                sqe,
+                IORING_OP_MSG_RING,
+                ring_fd,
+                IORING_MSG_DATA as usize,
+                len,
+                data,
+            )
+        };
+        sqe.__bindgen_anon_3.msg_ring_flags = flags;
+        self
+    }
+
+    /// Install the file in our fixed file table at `source_slot` in the
+    /// fixed file table of the ring `ring_fd`, at `target_slot`. Pass
+    /// `IORING_FILE_INDEX_ALLOC` to have the kernel pick a free slot.
+    /// The other ring gets a CQE with `data` as its user data and the slot
+    /// as its result, unless `flags` has `IORING_MSG_RING_CQE_SKIP`.
+    ///
+    /// Our slot is left as it is, the file is in both tables afterwards.
+    pub fn io_uring_prep_msg_ring_fd(
+        self,
+        ring_fd: RawFd,
+        source_slot: u32,
+        target_slot: u32,
+        data: u64,
+        flags: u32,
+    ) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe {
+            Self::io_uring_prep_rw(
+                sqe,
+                IORING_OP_MSG_RING,
+                ring_fd,
+                IORING_MSG_SEND_FD as usize,
+                0,
+                data,
+            )
+        };
+        unsafe { sqe.__bindgen_anon_6.__bindgen_anon_1.as_mut().addr3 = source_slot as u64 };
+        sqe.__bindgen_anon_3.msg_ring_flags = flags;
+        self.set_target_fixed_file(target_slot)
+    }
+
     // Missing prep_file_updates
-    // Missing prep_fallocate, openat, openat_direct, close, close_direct
+    /// Prepare a `fallocate` of `len` bytes at `offset` in `fd`, e.g., to
+    /// preallocate space for a file before writing it.
+    pub fn io_uring_prep_fallocate(self, fd: RawFd, mode: i32, offset: u64, len: u64) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_FALLOCATE, fd, 0, mode as u32, offset) };
+        sqe.__bindgen_anon_2.addr = len;
+        self
+    }
+
+    /// Prepare an `openat`, opening `path` relative to `dfd` (which can be
+    /// `libc::AT_FDCWD`). The path is copied, and so need not outlive this call.
+    /// The CQE result is the new FD.
+    pub fn io_uring_prep_openat(
+        self,
+        dfd: RawFd,
+        path: &CStr,
+        flags: i32,
+        mode: libc::mode_t,
+    ) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        let path = self.stash_path(0, path);
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_OPENAT, dfd, path, mode, 0) };
+        sqe.__bindgen_anon_3.open_flags = flags as u32;
+        self
+    }
+
+    /// Prepare an `openat` that installs the file in the fixed file table at
+    /// `file_index` rather than returning a FD. Pass `IORING_FILE_INDEX_ALLOC`
+    /// to have the kernel pick a free slot, which is returned as the CQE
+    /// result. `O_CLOEXEC` is not supported for direct descriptors.
+    pub fn io_uring_prep_openat_direct(
+        self,
+        dfd: RawFd,
+        path: &CStr,
+        flags: i32,
+        mode: libc::mode_t,
+        file_index: u32,
+    ) -> Self {
+        self.io_uring_prep_openat(dfd, path, flags, mode)
+            .set_target_fixed_file(file_index)
+    }
+
+    /// Prepare an `openat2`, opening `path` relative to `dfd` as described
+    /// by `how`. Both `path` and `how` are copied.
+    pub fn io_uring_prep_openat2(self, dfd: RawFd, path: &CStr, how: &open_how) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        let path = self.stash_path(0, path);
+        let how = unsafe {
+            (*self.args).how = *how;
+            &(*self.args).how as *const open_how as u64
+        };
+        unsafe {
+            Self::io_uring_prep_rw(
+                sqe,
+                IORING_OP_OPENAT2,
+                dfd,
+                path,
+                std::mem::size_of::<open_how>() as u32,
+                how,
+            )
+        };
+        self
+    }
+
+    /// Prepare an `openat2` that installs the file in the fixed file table,
+    /// see `io_uring_prep_openat_direct`.
+    pub fn io_uring_prep_openat2_direct(
+        self,
+        dfd: RawFd,
+        path: &CStr,
+        how: &open_how,
+        file_index: u32,
+    ) -> Self {
+        self.io_uring_prep_openat2(dfd, path, how)
+            .set_target_fixed_file(file_index)
+    }
+
+    /// Prepare a rename of `oldpath` (relative to `olddfd`) to `newpath`
+    /// (relative to `newdfd`). Both paths are copied.
+    pub fn io_uring_prep_renameat(
+        self,
+        olddfd: RawFd,
+        oldpath: &CStr,
+        newdfd: RawFd,
+        newpath: &CStr,
+        flags: RenameFlags,
+    ) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        let oldpath = self.stash_path(0, oldpath);
+        let newpath = self.stash_path(1, newpath);
+        unsafe {
+            Self::io_uring_prep_rw(
+                sqe,
+                IORING_OP_RENAMEAT,
+                olddfd,
+                oldpath,
+                newdfd as u32,
+                newpath as u64,
+            )
+        };
+        sqe.__bindgen_anon_3.rename_flags = flags as u32;
+        self
+    }
+
+    /// Prepare removal of `path` (relative to `dfd`). The path is copied.
+    pub fn io_uring_prep_unlinkat(self, dfd: RawFd, path: &CStr, flags: UnlinkFlags) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        let path = self.stash_path(0, path);
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_UNLINKAT, dfd, path, 0, 0) };
+        sqe.__bindgen_anon_3.unlink_flags = flags as u32;
+        self
+    }
+
+    /// Prepare creation of directory `path` (relative to `dfd`). The path
+    /// is copied.
+    pub fn io_uring_prep_mkdirat(self, dfd: RawFd, path: &CStr, mode: libc::mode_t) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        let path = self.stash_path(0, path);
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_MKDIRAT, dfd, path, mode, 0) };
+        self
+    }
+
+    /// Prepare creation of a symlink at `linkpath` (relative to `newdfd`)
+    /// pointing to `target`. Both paths are copied.
+    pub fn io_uring_prep_symlinkat(self, target: &CStr, newdfd: RawFd, linkpath: &CStr) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        let target = self.stash_path(0, target);
+        let linkpath = self.stash_path(1, linkpath);
+        unsafe {
+            Self::io_uring_prep_rw(sqe, IORING_OP_SYMLINKAT, newdfd, target, 0, linkpath as u64)
+        };
+        self
+    }
+
+    /// Prepare creation of a hard link at `newpath` (relative to `newdfd`)
+    /// to `oldpath` (relative to `olddfd`). `flags` can include
+    /// `libc::AT_SYMLINK_FOLLOW`. Both paths are copied.
+    pub fn io_uring_prep_linkat(
+        self,
+        olddfd: RawFd,
+        oldpath: &CStr,
+        newdfd: RawFd,
+        newpath: &CStr,
+        flags: i32,
+    ) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        let oldpath = self.stash_path(0, oldpath);
+        let newpath = self.stash_path(1, newpath);
+        unsafe {
+            Self::io_uring_prep_rw(
+                sqe,
+                IORING_OP_LINKAT,
+                olddfd,
+                oldpath,
+                newdfd as u32,
+                newpath as u64,
+            )
+        };
+        sqe.__bindgen_anon_3.hardlink_flags = flags as u32;
+        self
+    }
+
+    /// Prepare a close of `fd`.
+    pub fn io_uring_prep_close(self, fd: RawFd) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_CLOSE, fd, 0, 0, 0) };
+        self
+    }
+
+    /// Prepare a close of the direct descriptor in slot `file_index` of the
+    /// fixed file table.
+    pub fn io_uring_prep_close_direct(self, file_index: u32) -> Self {
+        self.io_uring_prep_close(0)
+            .set_target_fixed_file(file_index)
+    }
 
     /// Prepare read from `fd` into `buf` starting at `offset`.
     ///
@@ -476,7 +1040,66 @@ impl Sqe<'_> {
         self
     }
 
-    // Missin statx, fadvise, madvise
+    /// Prepare a `statx` of `path` relative to `dfd`, storing the result in
+    /// `buf`. `mask` is a set of `libc::STATX_*` fields to fetch. Use
+    /// `op::Statx` from outside the crate.
+    ///
+    /// Unlike other ops taking a path, this does not copy it to the SQE's
+    /// slot, as before Linux 5.18 the kernel only reads the path once the
+    /// `statx` runs, by which time the slot may have been reused.
+    ///
+    /// # Safety
+    /// `path` and `buf` must stay valid until the operation completes.
+    pub(crate) unsafe fn io_uring_prep_statx(
+        self,
+        dfd: RawFd,
+        path: *const libc::c_char,
+        flags: i32,
+        mask: u32,
+        buf: *mut libc::statx,
+    ) -> Self {
+        let sqe = &mut (*self.sqe);
+        Self::io_uring_prep_rw(sqe, IORING_OP_STATX, dfd, path as usize, mask, buf as u64);
+        sqe.__bindgen_anon_3.statx_flags = flags as u32;
+        self
+    }
+
+    /// Prepare a `posix_fadvise` of `len` bytes at `offset` in `fd`, with
+    /// `advice` being one of `libc::POSIX_FADV_*`.
+    pub fn io_uring_prep_fadvise(self, fd: RawFd, offset: u64, len: u32, advice: i32) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_FADVISE, fd, 0, len, offset) };
+        sqe.__bindgen_anon_3.fadvise_advice = advice as u32;
+        self
+    }
+
+    /// Prepare a `madvise` of `len` bytes at `addr`, with `advice` being
+    /// one of `libc::MADV_*`.
+    ///
+    /// # Safety
+    /// Some advice (e.g., `MADV_DONTNEED`) changes the contents of memory,
+    /// and the caller must ensure this is sound.
+    pub unsafe fn io_uring_prep_madvise(self, addr: *mut u8, len: u32, advice: i32) -> Self {
+        let sqe = &mut (*self.sqe);
+        Self::io_uring_prep_rw(sqe, IORING_OP_MADVISE, -1, addr as usize, len, 0);
+        sqe.__bindgen_anon_3.fadvise_advice = advice as u32;
+        self
+    }
+
+    /// Prepare a `sync_file_range` of `len` bytes at `offset` in `fd`, with
+    /// `flags` being a set of `libc::SYNC_FILE_RANGE_*`.
+    pub fn io_uring_prep_sync_file_range(
+        self,
+        fd: RawFd,
+        len: u32,
+        offset: u64,
+        flags: u32,
+    ) -> Self {
+        let sqe = unsafe { &mut (*self.sqe) };
+        unsafe { Self::io_uring_prep_rw(sqe, IORING_OP_SYNC_FILE_RANGE, fd, 0, len, offset) };
+        sqe.__bindgen_anon_3.sync_range_flags = flags;
+        self
+    }
 
     /// Prepare a send
     ///
@@ -524,24 +1147,17 @@ impl Sqe<'_> {
         self
     }
 
-    /// Prepare a zero-copy (actually minimal copy) send.
+    /// Prepare a zero-copy (actually minimal copy) send of the contents of
+    /// the fixed buffer `buf`.
     /// Note that in this case one might receive multiple CQEs for the
     /// same request (with `expect_more_notifications` set to true), and
     /// the call cannot be considered complete until the final CQE.
     ///
     /// # Safety
-    /// `buf` must be `len` bytes, and must have been previously registered
-    /// at the given `buf_index` using `io_uring_register_buffers`.
-    pub unsafe fn io_uring_prep_send_zc_fixed<T>(
-        self,
-        fd: RawFd,
-        buf: *const T,
-        len: usize,
-        flags: u32,
-        buf_index: u16,
-    ) -> Self {
-        self.io_uring_prep_send_zc(fd, buf, len, flags)
-            .set_fixed_buf(buf_index)
+    /// `buf` must not be dropped or modified until the final CQE.
+    pub unsafe fn io_uring_prep_send_zc_fixed(self, fd: RawFd, buf: &FixedBuf, flags: u32) -> Self {
+        self.io_uring_prep_send_zc(fd, buf.as_ptr(), buf.len(), flags)
+            .set_fixed_buf(buf.buf_index())
     }
 
     /// Prepare a receive. Data is received in `buf`.
@@ -564,8 +1180,32 @@ impl Sqe<'_> {
     /// Prepare a multishot receive.
     pub fn io_uring_prep_recv_multishot(self, fd: RawFd, group_id: u16, flags: u32) -> Self {
         let s = unsafe { self.io_uring_prep_recv::<u8>(fd, std::ptr::null_mut(), 0, flags) };
-        s.set_multishot().set_buffer_select(group_id)
+        s.set_recv_multishot().set_buffer_select(group_id)
     }
     /// Indicate that we are done with the SQE.
     pub fn finalize(self) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cmd_covers_end_of_sqe() {
+        let offset = std::mem::offset_of!(io_uring_sqe, __bindgen_anon_6);
+        for (big, len) in [(false, 16), (true, 80)] {
+            // Room for a big SQE.
+            let mut mem = [0u64; 16];
+            let mut args = SqeArgs::default();
+            let mut sqe = unsafe { Sqe::init(mem.as_mut_ptr().cast(), &mut args, big) };
+            assert_eq!(sqe.is_big(), big);
+            assert_eq!(sqe.cmd_len(), len);
+            assert_eq!(sqe.cmd().len(), len);
+            sqe.cmd_mut().fill(0xff);
+            let bytes: &[u8] = unsafe { std::slice::from_raw_parts(mem.as_ptr().cast(), 128) };
+            assert!(bytes[..offset].iter().all(|b| *b == 0));
+            assert!(bytes[offset..offset + len].iter().all(|b| *b == 0xff));
+            assert!(bytes[offset + len..].iter().all(|b| *b == 0));
+        }
+    }
+}
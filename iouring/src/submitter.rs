@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+
+use super::*;
+
+/// Set in the `user_data` of the SQEs handed out by a [`Submitter`]. Keep it
+/// set when setting the user data of such an SQE, and do not set it for
+/// other requests on the same ring.
+pub const SUBMITTER_USER_DATA_TAG: u64 = 1 << 61;
+
+/// Hands out SQEs while keeping the number of requests in flight within
+/// what the CQ can hold, so that a burst of completions cannot overflow it.
+///
+/// Every request is assumed to post one final CQE, i.e., one without
+/// `IORING_CQE_F_MORE`, so requests must not use `set_no_cqe`. Multishot
+/// requests count as one request, but may still post more CQEs than fit.
+///
+/// CQEs must be reaped through the `Submitter`, which keeps those it reaps
+/// while waiting for room in a backlog. Only CQEs with
+/// [`SUBMITTER_USER_DATA_TAG`] set count as completing a request, so those
+/// of other requests on the ring, e.g., messages from other rings, do not.
+///
+/// ```ignore
+/// let mut submitter = Submitter::new(&mut ring);
+/// // `chunks` is kept until all writes complete.
+/// for (i, (off, buf)) in chunks.iter().enumerate() {
+///     let sqe = submitter.get_sqe()?;
+///     unsafe { sqe.io_uring_prep_write_fixed(fd, buf, *off) }
+///         .set_sqe_data(i as u64 | SUBMITTER_USER_DATA_TAG)
+///         .finalize();
+///     while let Some(cqe) = submitter.next_cqe()? {
+///         cqe.result()?;
+///     }
+/// }
+/// ```
+pub struct Submitter<'a> {
+    ring: &'a mut IoUring,
+    // Requests handed out, including those not submitted yet, that have
+    // not posted their final CQE.
+    in_flight: u32,
+    limit: u32,
+    blocking: bool,
+    backlog: VecDeque<Cqe>,
+}
+
+impl Submitter<'_> {
+    /// Returns a blocking submitter allowing as many requests in flight as
+    /// the CQ has entries.
+    pub fn new(ring: &mut IoUring) -> Submitter<'_> {
+        let limit = ring.params.cq_entries;
+        Submitter {
+            ring,
+            in_flight: 0,
+            limit,
+            blocking: true,
+            backlog: VecDeque::new(),
+        }
+    }
+
+    /// Allow at most `limit` requests in flight, e.g., to leave room in the
+    /// CQ for requests submitted elsewhere.
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = limit.clamp(1, self.ring.params.cq_entries);
+        self
+    }
+
+    /// Have `get_sqe` fail with `WouldBlock` rather than wait for
+    /// completions when the limit is reached.
+    pub fn nonblocking(mut self) -> Self {
+        self.blocking = false;
+        self
+    }
+
+    /// Returns the number of requests in flight.
+    pub fn in_flight(&self) -> u32 {
+        self.in_flight
+    }
+
+    /// Returns a SQE for a new request, submitting queued SQEs if the SQ
+    /// is full. If the limit of requests in flight is reached, this first
+    /// waits for completions, or fails with `WouldBlock` if nonblocking.
+    /// The SQE has `SUBMITTER_USER_DATA_TAG` as its user data.
+    pub fn get_sqe(&mut self) -> std::io::Result<Sqe<'_>> {
+        self.reap()?;
+        while self.in_flight >= self.limit {
+            if !self.blocking {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            self.submit()?;
+            let cqes = io_uring_wait_cqe(self.ring)?;
+            reap(cqes, &mut self.in_flight, &mut self.backlog);
+        }
+        let sqe = self.ring.get_sqe_or_submit()?;
+        self.in_flight += 1;
+        Ok(sqe.set_sqe_data(SUBMITTER_USER_DATA_TAG))
+    }
+
+    /// Submit queued SQEs, returning how many were submitted.
+    pub fn submit(&mut self) -> std::io::Result<u32> {
+        let ret = self.ring.submit();
+        if ret < 0 {
+            Err(std::io::Error::from_raw_os_error(-ret))
+        } else {
+            Ok(ret as u32)
+        }
+    }
+
+    /// Returns the next CQE if one is available, without waiting.
+    pub fn next_cqe(&mut self) -> std::io::Result<Option<Cqe>> {
+        if self.backlog.is_empty() {
+            self.reap()?;
+        }
+        Ok(self.backlog.pop_front())
+    }
+
+    /// Submit queued SQEs and return the next CQE, waiting for one if
+    /// needed.
+    pub fn wait_cqe(&mut self) -> std::io::Result<Cqe> {
+        loop {
+            if let Some(cqe) = self.next_cqe()? {
+                return Ok(cqe);
+            }
+            self.submit()?;
+            let cqes = io_uring_wait_cqe(self.ring)?;
+            reap(cqes, &mut self.in_flight, &mut self.backlog);
+        }
+    }
+
+    /// Move available CQEs to the backlog.
+    fn reap(&mut self) -> std::io::Result<()> {
+        let cqes = unsafe { io_uring_peek_cqe(self.ring) }?;
+        reap(cqes, &mut self.in_flight, &mut self.backlog);
+        Ok(())
+    }
+}
+
+fn reap(cqes: Option<CqeJar>, in_flight: &mut u32, backlog: &mut VecDeque<Cqe>) {
+    for cqe in cqes.into_iter().flatten() {
+        // `LIBURING_UDATA_TIMEOUT` has all bits set.
+        let ours =
+            cqe.user_data & SUBMITTER_USER_DATA_TAG != 0 && cqe.user_data != LIBURING_UDATA_TIMEOUT;
+        if ours && !cqe.expect_more_notifications() {
+            *in_flight = in_flight.saturating_sub(1);
+        }
+        backlog.push_back(cqe);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeRing;
+
+    #[test]
+    fn counts_tagged_final_cqes() {
+        let mut ring = FakeRing::new(4, 0);
+        let mut submitter = Submitter::new(&mut ring);
+        for i in 0..3 {
+            let sqe = submitter.get_sqe().unwrap();
+            sqe.set_sqe_data(i | SUBMITTER_USER_DATA_TAG).finalize();
+        }
+        assert_eq!(submitter.in_flight(), 3);
+        drop(submitter);
+
+        ring.post_cqe(SUBMITTER_USER_DATA_TAG, 0, IORING_CQE_F_MORE);
+        ring.post_cqe(1 | SUBMITTER_USER_DATA_TAG, 0, 0);
+        // From another ring, or a timeout.
+        ring.post_cqe(7, 0, 0);
+        ring.post_cqe(LIBURING_UDATA_TIMEOUT, -libc::ETIME, 0);
+        let mut submitter = Submitter::new(&mut ring);
+        submitter.in_flight = 3;
+        let mut seen = Vec::new();
+        while let Some(cqe) = submitter.next_cqe().unwrap() {
+            seen.push(cqe.user_data);
+        }
+        assert_eq!(seen.len(), 4);
+        assert_eq!(submitter.in_flight(), 2);
+    }
+
+    #[test]
+    fn nonblocking_at_limit() {
+        let mut ring = FakeRing::new(4, 0);
+        let mut submitter = Submitter::new(&mut ring).limit(2).nonblocking();
+        submitter.get_sqe().unwrap().io_uring_prep_nop().finalize();
+        submitter.get_sqe().unwrap().io_uring_prep_nop().finalize();
+        let err = submitter.get_sqe().err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+        assert_eq!(submitter.in_flight(), 2);
+        drop(submitter);
+
+        ring.post_cqe(SUBMITTER_USER_DATA_TAG, 0, 0);
+        let mut submitter = Submitter::new(&mut ring).limit(2).nonblocking();
+        submitter.in_flight = 2;
+        assert!(submitter.get_sqe().is_ok());
+        assert_eq!(submitter.in_flight(), 2);
+        assert_eq!(
+            submitter.next_cqe().unwrap().unwrap().user_data,
+            SUBMITTER_USER_DATA_TAG
+        );
+    }
+
+    #[test]
+    fn limit_is_clamped() {
+        let mut ring = FakeRing::new(4, 0);
+        assert_eq!(Submitter::new(&mut ring).limit, 8);
+        assert_eq!(Submitter::new(&mut ring).limit(0).limit, 1);
+        assert_eq!(Submitter::new(&mut ring).limit(100).limit, 8);
+    }
+}